@@ -0,0 +1,109 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+
+struct Shared<T> {
+    batches: VecDeque<Vec<T>>,
+    waker: Option<Waker>,
+    closed: bool,
+    stream_dropped: bool,
+}
+
+/// Sending half kept by the insert function of
+/// [`Inserter::batching_stream`](crate::Inserter::batching_stream).
+pub struct BatchSender<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> BatchSender<T> {
+    /// Queues `batch` for the stream, or drops it if the stream is gone.
+    pub fn send(&self, batch: Vec<T>) {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        if shared.stream_dropped {
+            return;
+        }
+        shared.batches.push_back(batch);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for BatchSender<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.closed = true;
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Stream of the batches flushed by an inserter built with
+/// [`Inserter::batching_stream`](crate::Inserter::batching_stream).
+///
+/// Ends once the inserter has been dropped or ended and every batch has been
+/// yielded.
+pub struct BatchStream<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+pub fn channel<T>() -> (BatchSender<T>, BatchStream<T>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        batches: VecDeque::new(),
+        waker: None,
+        closed: false,
+        stream_dropped: false,
+    }));
+    (
+        BatchSender {
+            shared: Arc::clone(&shared),
+        },
+        BatchStream { shared },
+    )
+}
+
+impl<T> Stream for BatchStream<T> {
+    type Item = Vec<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(batch) = shared.batches.pop_front() {
+            return Poll::Ready(Some(batch));
+        }
+        if shared.closed {
+            return Poll::Ready(None);
+        }
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for BatchStream<T> {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.stream_dropped = true;
+        shared.batches.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_after_stream_dropped() {
+        let row = Arc::new(());
+        let (sender, stream) = channel();
+        sender.send(vec![Arc::clone(&row)]);
+        assert_eq!(Arc::strong_count(&row), 2);
+
+        drop(stream);
+        assert_eq!(Arc::strong_count(&row), 1);
+        sender.send(vec![Arc::clone(&row)]);
+        assert_eq!(Arc::strong_count(&row), 1);
+    }
+}
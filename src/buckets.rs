@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+type Timestamp<T> = Box<dyn Fn(&T) -> Instant + Send>;
+
+/// Assigns items to fixed-width time buckets aligned to the timestamp of the
+/// first item seen. A bucket is closed once the clock passes its end.
+pub struct TimeBuckets<T> {
+    width: Duration,
+    timestamp: Timestamp<T>,
+    epoch: Option<Instant>,
+    oldest: Option<i128>,
+}
+
+impl<T> TimeBuckets<T> {
+    pub fn new(width: Duration, timestamp: Timestamp<T>) -> Self {
+        Self {
+            width,
+            timestamp,
+            epoch: None,
+            oldest: None,
+        }
+    }
+
+    pub fn record(&mut self, item: &T) {
+        let index = self.index(item);
+        self.oldest = Some(self.oldest.map_or(index, |oldest| oldest.min(index)));
+    }
+
+    /// Forgets the recorded items, e.g. after the buffer was discarded.
+    pub const fn clear(&mut self) {
+        self.oldest = None;
+    }
+
+    pub fn has_closed(&self, now: Instant) -> bool {
+        self.oldest.is_some_and(|index| self.is_closed(index, now))
+    }
+
+    /// Splits `items` into closed buckets, in bucket order, and the rows of
+    /// buckets that are still open.
+    pub fn split_closed(&mut self, items: Vec<T>, now: Instant) -> (Vec<Vec<T>>, Vec<T>) {
+        let mut closed: BTreeMap<i128, Vec<T>> = BTreeMap::new();
+        let mut open = Vec::new();
+        self.oldest = None;
+
+        for item in items {
+            let index = self.index(&item);
+            if self.is_closed(index, now) {
+                closed.entry(index).or_default().push(item);
+            } else {
+                self.oldest = Some(self.oldest.map_or(index, |oldest| oldest.min(index)));
+                open.push(item);
+            }
+        }
+
+        (closed.into_values().collect(), open)
+    }
+
+    /// Splits `items` into one batch per bucket, in bucket order.
+    pub fn split_all(&mut self, items: Vec<T>) -> Vec<Vec<T>> {
+        let mut buckets: BTreeMap<i128, Vec<T>> = BTreeMap::new();
+        for item in items {
+            buckets.entry(self.index(&item)).or_default().push(item);
+        }
+        self.oldest = None;
+        buckets.into_values().collect()
+    }
+
+    fn index(&mut self, item: &T) -> i128 {
+        let timestamp = (self.timestamp)(item);
+        let epoch = *self.epoch.get_or_insert(timestamp);
+        Self::offset(timestamp, epoch).div_euclid(self.width_nanos())
+    }
+
+    fn is_closed(&self, index: i128, now: Instant) -> bool {
+        self.epoch.is_some_and(|epoch| {
+            (index + 1).saturating_mul(self.width_nanos()) <= Self::offset(now, epoch)
+        })
+    }
+
+    fn width_nanos(&self) -> i128 {
+        i128::try_from(self.width.as_nanos()).unwrap_or(i128::MAX)
+    }
+
+    fn offset(instant: Instant, epoch: Instant) -> i128 {
+        if instant >= epoch {
+            i128::try_from((instant - epoch).as_nanos()).unwrap_or(i128::MAX)
+        } else {
+            -i128::try_from((epoch - instant).as_nanos()).unwrap_or(i128::MAX)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buckets() -> TimeBuckets<Instant> {
+        TimeBuckets::new(Duration::from_secs(1), Box::new(|ts: &Instant| *ts))
+    }
+
+    #[test]
+    fn test_split_closed() {
+        let start = Instant::now();
+        let mut buckets = buckets();
+
+        let items = vec![
+            start,
+            start + Duration::from_millis(1500),
+            start + Duration::from_millis(500),
+        ];
+        for item in &items {
+            buckets.record(item);
+        }
+
+        assert!(!buckets.has_closed(start + Duration::from_millis(999)));
+        assert!(buckets.has_closed(start + Duration::from_secs(1)));
+
+        let (closed, open) = buckets.split_closed(items, start + Duration::from_millis(1200));
+        assert_eq!(
+            closed,
+            vec![vec![start, start + Duration::from_millis(500)]]
+        );
+        assert_eq!(open, vec![start + Duration::from_millis(1500)]);
+        assert!(!buckets.has_closed(start + Duration::from_millis(1999)));
+        assert!(buckets.has_closed(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_split_all_orders_buckets() {
+        let earlier = Instant::now();
+        let start = earlier + Duration::from_millis(10);
+        let mut buckets = buckets();
+        buckets.record(&start);
+
+        let later = start + Duration::from_secs(3);
+        let batches = buckets.split_all(vec![later, start, earlier]);
+
+        assert_eq!(batches, vec![vec![earlier], vec![start], vec![later]]);
+    }
+}
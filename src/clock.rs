@@ -0,0 +1,17 @@
+use std::fmt::Debug;
+use std::time::Instant;
+
+/// Source of the current instant for time-based limits.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Clock backed by [`Instant::now`]; used when no clock is configured.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
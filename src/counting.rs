@@ -0,0 +1,35 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll, ready};
+
+/// Future returned by the insert function of an inserter built with
+/// [`Inserter::new_counting`](crate::Inserter::new_counting).
+pub struct Counting<Fut> {
+    future: Pin<Box<Fut>>,
+    affected: Arc<AtomicU64>,
+}
+
+impl<Fut> Counting<Fut> {
+    pub(crate) fn new(future: Fut, affected: Arc<AtomicU64>) -> Self {
+        Self {
+            future: Box::pin(future),
+            affected,
+        }
+    }
+}
+
+impl<Fut, E> Future for Counting<Fut>
+where
+    Fut: Future<Output = Result<u64, E>>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let affected = ready!(this.future.as_mut().poll(cx))?;
+        this.affected.fetch_add(affected, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+pub trait DedupFilter<T>: Send {
+    /// Returns `false` if an item with the same key was admitted recently.
+    fn admit(&mut self, item: &T, now: Instant) -> bool;
+
+    /// Forgets the key of `item`, e.g. because the item was dropped after
+    /// being admitted.
+    fn forget(&mut self, item: &T);
+
+    /// Number of remembered keys, including expired ones not yet evicted.
+    fn len(&self) -> usize;
+
+    /// Forgets every remembered key.
+    fn clear(&mut self);
+}
+
+/// Remembers keys of admitted items until they are older than `ttl` or pushed
+/// out by newer keys once `capacity` is exceeded, oldest first.
+pub struct CrossBatchDedup<K, KF> {
+    key_fn: KF,
+    capacity: usize,
+    ttl: Duration,
+    seen: HashMap<K, Instant>,
+    order: VecDeque<(K, Instant)>,
+}
+
+impl<K, KF> CrossBatchDedup<K, KF>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new(key_fn: KF, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            key_fn,
+            capacity,
+            ttl,
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    fn evict(&mut self, now: Instant) {
+        while let Some((key, seen_at)) = self.order.front() {
+            let expired = now.saturating_duration_since(*seen_at) >= self.ttl;
+            if !expired && self.order.len() <= self.capacity {
+                break;
+            }
+            if self.seen.get(key) == Some(seen_at) {
+                self.seen.remove(key);
+            }
+            self.order.pop_front();
+        }
+    }
+}
+
+impl<T, K, KF> DedupFilter<T> for CrossBatchDedup<K, KF>
+where
+    K: Hash + Eq + Clone + Send,
+    KF: FnMut(&T) -> K + Send,
+{
+    fn admit(&mut self, item: &T, now: Instant) -> bool {
+        self.evict(now);
+
+        let key = (self.key_fn)(item);
+        if self.seen.contains_key(&key) {
+            return false;
+        }
+
+        self.seen.insert(key.clone(), now);
+        self.order.push_back((key, now));
+        self.evict(now);
+        true
+    }
+
+    fn forget(&mut self, item: &T) {
+        let key = (self.key_fn)(item);
+        if self.seen.remove(&key).is_some() {
+            self.order.retain(|(seen, _)| *seen != key);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    fn clear(&mut self) {
+        self.seen.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suppresses_repeated_keys() {
+        let now = Instant::now();
+        let mut dedup = CrossBatchDedup::new(|key: &u64| *key, 10, Duration::from_secs(60));
+
+        assert!(dedup.admit(&1, now));
+        assert!(!dedup.admit(&1, now));
+        assert!(dedup.admit(&2, now));
+    }
+
+    #[test]
+    fn test_capacity_evicts_oldest() {
+        let now = Instant::now();
+        let mut dedup = CrossBatchDedup::new(|key: &u64| *key, 2, Duration::from_secs(60));
+
+        assert!(dedup.admit(&1, now));
+        assert!(dedup.admit(&2, now));
+        assert!(dedup.admit(&3, now));
+
+        assert!(dedup.admit(&1, now));
+        assert!(!dedup.admit(&3, now));
+    }
+
+    #[test]
+    fn test_ttl_expires_keys() {
+        let now = Instant::now();
+        let mut dedup = CrossBatchDedup::new(|key: &u64| *key, 10, Duration::from_secs(5));
+
+        assert!(dedup.admit(&1, now));
+        assert!(!dedup.admit(&1, now + Duration::from_secs(4)));
+        assert!(dedup.admit(&1, now + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_forget_readmits_key() {
+        let now = Instant::now();
+        let mut dedup = CrossBatchDedup::new(|key: &u64| *key, 2, Duration::from_secs(60));
+
+        assert!(dedup.admit(&1, now));
+        dedup.forget(&1);
+        assert!(dedup.admit(&1, now));
+        assert!(dedup.admit(&2, now));
+        assert!(!dedup.admit(&1, now));
+    }
+}
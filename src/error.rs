@@ -1,30 +1,107 @@
 use std::error::Error;
 use std::fmt;
+#[cfg(feature = "tokio")]
+use std::time::Duration;
 
 #[derive(Debug)]
-pub struct InserterError<E: Error> {
-    source: E,
+#[non_exhaustive]
+pub enum InserterError<E: Error> {
+    /// The insert function failed.
+    Insert { source: E, attempts: u32 },
+    /// An insert did not complete within the limit set with
+    /// [`Inserter::with_insert_timeout`](crate::Inserter::with_insert_timeout).
+    #[cfg(feature = "tokio")]
+    Timeout { timeout: Duration, attempts: u32 },
 }
 
 impl<E: Error> InserterError<E> {
     pub const fn new(source: E) -> Self {
-        Self { source }
+        Self::Insert {
+            source,
+            attempts: 1,
+        }
     }
 
+    #[cfg(feature = "tokio")]
+    pub(crate) const fn timeout(timeout: Duration) -> Self {
+        Self::Timeout {
+            timeout,
+            attempts: 1,
+        }
+    }
+
+    pub(crate) const fn with_attempts(mut self, attempts: u32) -> Self {
+        match &mut self {
+            Self::Insert {
+                attempts: tried, ..
+            } => *tried = attempts,
+            #[cfg(feature = "tokio")]
+            Self::Timeout {
+                attempts: tried, ..
+            } => *tried = attempts,
+        }
+        self
+    }
+
+    /// Number of times the failed batch was tried, more than one with
+    /// retries enabled.
+    pub const fn attempts(&self) -> u32 {
+        match self {
+            Self::Insert { attempts, .. } => *attempts,
+            #[cfg(feature = "tokio")]
+            Self::Timeout { attempts, .. } => *attempts,
+        }
+    }
+
+    pub const fn is_timeout(&self) -> bool {
+        match self {
+            Self::Insert { .. } => false,
+            #[cfg(feature = "tokio")]
+            Self::Timeout { .. } => true,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics on a timeout, which carries no error of the insert function;
+    /// use [`into_insert_error`](Self::into_insert_error) with
+    /// `with_insert_timeout`.
     pub fn into_inner(self) -> E {
-        self.source
+        self.into_insert_error()
+            .expect("a timed out insert has no insert error")
+    }
+
+    /// The error of the insert function, `None` for a timeout.
+    pub fn into_insert_error(self) -> Option<E> {
+        match self {
+            Self::Insert { source, .. } => Some(source),
+            #[cfg(feature = "tokio")]
+            Self::Timeout { .. } => None,
+        }
     }
 }
 
 impl<E: Error> fmt::Display for InserterError<E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "inserter error: {}", self.source)
+        f.write_str("inserter error")?;
+        if self.attempts() > 1 {
+            write!(f, " after {} attempts", self.attempts())?;
+        }
+        match self {
+            Self::Insert { source, .. } => write!(f, ": {source}"),
+            #[cfg(feature = "tokio")]
+            Self::Timeout { timeout, .. } => write!(f, ": insert timed out after {timeout:?}"),
+        }
     }
 }
 
 impl<E: Error + 'static> Error for InserterError<E> {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(&self.source)
+        match self {
+            Self::Insert { source, .. } => Some(source),
+            #[cfg(feature = "tokio")]
+            Self::Timeout { .. } => None,
+        }
     }
 }
 
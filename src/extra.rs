@@ -0,0 +1,212 @@
+use std::error::Error;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::error::InserterError;
+use crate::inserter::Inserter;
+use crate::quantities::Quantities;
+
+/// Inserter returned by [`Inserter::with_extra_counter`], whose flushing
+/// methods also return the custom counter folded over the rows they flushed.
+///
+/// Builders are applied with [`configure`](Self::configure) and everything
+/// else is reachable through `Deref`. Rows flushed through other methods of
+/// the wrapped inserter are counted in the result of the next flushing method
+/// called here.
+pub struct ExtraInserter<T, F, Fut, E, X>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    inserter: Inserter<T, F, Fut, E>,
+    counter: Counter<X>,
+}
+
+impl<T, F, Fut, E, X> ExtraInserter<T, F, Fut, E, X>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+    X: Default,
+{
+    pub(crate) const fn new(inserter: Inserter<T, F, Fut, E>, counter: Counter<X>) -> Self {
+        Self { inserter, counter }
+    }
+
+    /// Applies `builder` to the wrapped inserter.
+    #[must_use]
+    pub fn configure<B>(self, builder: B) -> Self
+    where
+        B: FnOnce(Inserter<T, F, Fut, E>) -> Inserter<T, F, Fut, E>,
+    {
+        Self {
+            inserter: builder(self.inserter),
+            counter: self.counter,
+        }
+    }
+
+    /// [`Inserter::commit`] that also returns the counter of the flushed rows,
+    /// `X::default()` if nothing was flushed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn commit(&mut self) -> Result<(Quantities, X), InserterError<E>> {
+        let result = self.inserter.commit().await;
+        self.with_extra(result)
+    }
+
+    /// [`Inserter::force_commit`] that also returns the counter of the
+    /// flushed rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn force_commit(&mut self) -> Result<(Quantities, X), InserterError<E>> {
+        let result = self.inserter.force_commit().await;
+        self.with_extra(result)
+    }
+
+    /// [`Inserter::flush`] that also returns the counter of the flushed rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn flush(&mut self) -> Result<(Quantities, X), InserterError<E>> {
+        let result = self.inserter.flush().await;
+        self.with_extra(result)
+    }
+
+    /// [`Inserter::end`] that also returns the counter of the rows flushed by
+    /// the final flush.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn end(self) -> Result<(Quantities, X), InserterError<E>> {
+        let result = self.inserter.end().await;
+        let extra = self.counter.take();
+        result.map(|committed| (committed, extra))
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Inserter<T, F, Fut, E> {
+        self.inserter
+    }
+
+    /// Pairs `result` with the counter of the flushes it covers. A failed
+    /// call discards the counter, like the quantities it would have returned.
+    fn with_extra(
+        &self,
+        result: Result<Quantities, InserterError<E>>,
+    ) -> Result<(Quantities, X), InserterError<E>> {
+        let extra = self.counter.take();
+        result.map(|flushed| (flushed, extra))
+    }
+}
+
+impl<T, F, Fut, E, X> Deref for ExtraInserter<T, F, Fut, E, X>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    type Target = Inserter<T, F, Fut, E>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inserter
+    }
+}
+
+impl<T, F, Fut, E, X> DerefMut for ExtraInserter<T, F, Fut, E, X>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inserter
+    }
+}
+
+/// Counter state shared by an [`ExtraInserter`] and the folding side owned
+/// by its inserter.
+pub struct Counter<X> {
+    shared: Arc<Mutex<Shared<X>>>,
+}
+
+struct Shared<X> {
+    /// Counter of the flush being inserted, on top of `flushed`.
+    in_flight: Option<X>,
+    /// Counter of the flushes that succeeded since the last `take`.
+    flushed: Option<X>,
+}
+
+impl<X: Default> Counter<X> {
+    fn take(&self) -> X {
+        self.lock().flushed.take().unwrap_or_default()
+    }
+}
+
+impl<X> Counter<X> {
+    fn lock(&self) -> MutexGuard<'_, Shared<X>> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Type-erased folding side of a [`Counter`], owned by the inserter.
+pub trait ExtraFold<T>: Send {
+    /// Folds the rows of a flush that is about to be inserted.
+    fn start(&mut self, batches: &[Vec<T>]);
+
+    /// Adds the counter of the flush that just succeeded.
+    fn finish(&mut self);
+}
+
+struct Folder<X, G> {
+    counter: Counter<X>,
+    fold: G,
+}
+
+impl<T, X, G> ExtraFold<T> for Folder<X, G>
+where
+    X: Default + Clone + Send,
+    G: FnMut(&mut X, &T) + Send,
+{
+    fn start(&mut self, batches: &[Vec<T>]) {
+        // Folded into a copy, so that a failed flush leaves the counter of
+        // earlier flushes untouched.
+        let mut extra = self.counter.lock().flushed.clone().unwrap_or_default();
+        for item in batches.iter().flatten() {
+            (self.fold)(&mut extra, item);
+        }
+        self.counter.lock().in_flight = Some(extra);
+    }
+
+    fn finish(&mut self) {
+        let mut shared = self.counter.lock();
+        if let Some(extra) = shared.in_flight.take() {
+            shared.flushed = Some(extra);
+        }
+    }
+}
+
+pub fn extra_counter<T, X, G>(fold: G) -> (Box<dyn ExtraFold<T>>, Counter<X>)
+where
+    X: Default + Clone + Send + 'static,
+    G: FnMut(&mut X, &T) + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        in_flight: None,
+        flushed: None,
+    }));
+    let folder = Folder {
+        counter: Counter {
+            shared: Arc::clone(&shared),
+        },
+        fold,
+    };
+    (Box::new(folder), Counter { shared })
+}
@@ -0,0 +1,191 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker, ready};
+
+use tokio::sync::mpsc;
+
+/// Future returned by the insert function of an inserter built with
+/// [`Inserter::new_detached`](crate::Inserter::new_detached).
+pub type SendBatch = Pin<Box<dyn Future<Output = Result<(), FlusherClosed>> + Send>>;
+
+/// Bookkeeping shared by a detached inserter and its [`Flusher`].
+#[derive(Debug, Default)]
+pub struct Queue {
+    /// Rows handed to the channel and not yet inserted or lost.
+    rows: u64,
+    lost_rows: u64,
+    stopped: bool,
+    waker: Option<Waker>,
+}
+
+pub type SharedQueue = Arc<Mutex<Queue>>;
+
+fn lock(queue: &SharedQueue) -> MutexGuard<'_, Queue> {
+    queue.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Resolves once every queued row was inserted, or to `false` once the
+/// flusher stopped without inserting all of them.
+pub fn poll_drained(queue: &SharedQueue, cx: &Context<'_>) -> Poll<bool> {
+    let mut queue = lock(queue);
+    if queue.rows == 0 && queue.lost_rows == 0 {
+        return Poll::Ready(true);
+    }
+    if queue.stopped {
+        return Poll::Ready(false);
+    }
+    queue.waker = Some(cx.waker().clone());
+    Poll::Pending
+}
+
+pub fn channel<T, F, Fut, E>(
+    capacity: usize,
+    insert_fn: F,
+) -> (
+    impl FnMut(Vec<T>) -> SendBatch,
+    Flusher<T, F, Fut>,
+    SharedQueue,
+)
+where
+    T: Send + 'static,
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
+    let (sender, receiver) = mpsc::channel(capacity);
+    let queue = SharedQueue::default();
+    let shared = Arc::clone(&queue);
+    let send = move |batch: Vec<T>| {
+        let (sender, queue) = (sender.clone(), Arc::clone(&shared));
+        let rows = batch.len() as u64;
+        Box::pin(async move {
+            // Counted before sending, so the flusher never takes a batch
+            // that is not counted yet.
+            lock(&queue).rows += rows;
+            if sender.send(batch).await.is_err() {
+                let mut queue = lock(&queue);
+                queue.rows -= rows;
+                return Err(FlusherClosed {
+                    lost_rows: queue.lost_rows,
+                });
+            }
+            Ok(())
+        }) as SendBatch
+    };
+    let flusher = Flusher {
+        receiver,
+        insert_fn,
+        in_flight: None,
+        queue: Arc::clone(&queue),
+    };
+    (send, flusher, queue)
+}
+
+/// Error of a detached inserter once its [`Flusher`] has stopped, e.g. after
+/// an insert failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlusherClosed {
+    lost_rows: u64,
+}
+
+impl FlusherClosed {
+    /// Rows that were queued for the flusher but never inserted, including
+    /// the batch whose insert failed.
+    #[must_use]
+    pub const fn lost_rows(&self) -> u64 {
+        self.lost_rows
+    }
+}
+
+impl fmt::Display for FlusherClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "flusher has stopped, {} queued rows were not inserted",
+            self.lost_rows
+        )
+    }
+}
+
+impl Error for FlusherClosed {}
+
+/// Future inserting the batches queued by a detached inserter, see
+/// [`Inserter::new_detached`](crate::Inserter::new_detached).
+///
+/// Completes once the inserter is gone and the queue is drained, or with the
+/// first insert error.
+/// After an error, or if the flusher is dropped early, the batches still
+/// queued are discarded and their rows reported through [`FlusherClosed`].
+pub struct Flusher<T, F, Fut> {
+    receiver: mpsc::Receiver<Vec<T>>,
+    insert_fn: F,
+    in_flight: Option<(Pin<Box<Fut>>, u64)>,
+    queue: SharedQueue,
+}
+
+impl<T, F, Fut> Unpin for Flusher<T, F, Fut> {}
+
+impl<T, F, Fut> Flusher<T, F, Fut> {
+    /// Closes the queue and counts the rows of `failed` and of every batch
+    /// still queued as lost.
+    fn stop(&mut self, failed: u64) {
+        self.receiver.close();
+        let mut lost = failed;
+        while let Ok(batch) = self.receiver.try_recv() {
+            lost += batch.len() as u64;
+        }
+        let mut queue = lock(&self.queue);
+        queue.rows -= lost;
+        queue.lost_rows += lost;
+        queue.stopped = true;
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T, F, Fut, E> Future for Flusher<T, F, Fut>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            if let Some((insert, rows)) = this.in_flight.as_mut() {
+                let result = ready!(insert.as_mut().poll(cx));
+                let rows = *rows;
+                this.in_flight = None;
+                if let Err(err) = result {
+                    this.stop(rows);
+                    return Poll::Ready(Err(err));
+                }
+                let mut queue = lock(&this.queue);
+                queue.rows -= rows;
+                if queue.rows == 0 {
+                    if let Some(waker) = queue.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+
+            let Some(batch) = ready!(this.receiver.poll_recv(cx)) else {
+                this.stop(0);
+                return Poll::Ready(Ok(()));
+            };
+            let rows = batch.len() as u64;
+            this.in_flight = Some((Box::pin((this.insert_fn)(batch)), rows));
+        }
+    }
+}
+
+impl<T, F, Fut> Drop for Flusher<T, F, Fut> {
+    fn drop(&mut self) {
+        let in_flight = self.in_flight.take().map_or(0, |(_, rows)| rows);
+        self.stop(in_flight);
+    }
+}
@@ -1,15 +1,318 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::convert::Infallible;
 use std::error::Error;
-use std::future::Future;
+use std::future::{Future, Ready, poll_fn};
+use std::hash::Hash;
 use std::marker::PhantomData;
-use std::time::Duration;
+use std::num::NonZeroU64;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, ready};
+use std::thread;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "tokio")]
+use crate::flusher::{self, Flusher, FlusherClosed, SendBatch, SharedQueue};
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+#[cfg(feature = "stream")]
+use std::pin::pin;
+#[cfg(feature = "tokio")]
+use tokio::sync::{Notify, Semaphore, futures::OwnedNotified};
+#[cfg(feature = "tokio")]
+use tokio::time::{Sleep, sleep};
+
+#[cfg(feature = "stream")]
+use crate::batch_stream::{self, BatchStream};
+use crate::buckets::TimeBuckets;
+use crate::clock::Clock;
+use crate::counting::Counting;
+use crate::dedup::{CrossBatchDedup, DedupFilter};
 use crate::error::InserterError;
+use crate::extra::{ExtraFold, ExtraInserter, extra_counter};
+use crate::limits::Limits;
+use crate::map_err::MapErr;
+use crate::meta::MetaInserter;
+use crate::observer::Observer;
+use crate::overflow::OverflowPolicy;
 use crate::quantities::Quantities;
-use crate::ticks::Ticks;
+use crate::report::ShutdownReport;
+use crate::scheduler::Scheduler;
+#[cfg(feature = "sink")]
+use crate::sink::InserterSink;
+use crate::ticks::{Schedule, Ticks};
+use crate::transaction::Transaction;
+
+/// Upper bound for [`Inserter::with_auto_capacity`] preallocation.
+const MAX_AUTO_CAPACITY: usize = 1 << 20;
 
+/// Weight of the newest sample in the [`Inserter::ingest_rate`] moving
+/// average.
+const INGEST_RATE_SMOOTHING: f64 = 0.2;
+
+type NullInsert<T> = fn(Vec<T>) -> Ready<Result<(), Infallible>>;
 type CommitCallback = Box<dyn FnMut(&Quantities) + Send>;
+type CheckpointCallback = Box<dyn FnMut(u64) + Send>;
+type ErrorCallback<E> = Box<dyn FnMut(&E, &Quantities) + Send>;
+type CommitFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+type AsyncCommitCallback = Box<dyn FnMut(&Quantities) -> CommitFuture + Send>;
+type ScheduleMissCallback = Box<dyn FnMut(Duration) + Send>;
+type PreCommitCallback<T> = Box<dyn FnMut(&[T], &Quantities) -> bool + Send>;
+type Grouper<T> = Box<dyn FnMut(Vec<T>) -> Vec<Vec<T>> + Send>;
+type SizeFn<T> = Box<dyn Fn(&T) -> usize + Send>;
+type MemoryCheck = Box<dyn Fn() -> bool + Send>;
+type LimitFn<T> = Box<dyn FnMut(&Quantities, &[T]) -> bool + Send>;
+type Finalizer<T> = Box<dyn FnMut(&mut Vec<T>) + Send>;
+type CopyBatch<T> = fn(&[T]) -> Vec<T>;
+type RowsCommitCallback<T> = Box<dyn FnMut(&[T]) + Send>;
+type TeeFuture = Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send>>;
+type TeeFn<T> = Box<dyn FnMut(&[T]) -> TeeFuture + Send>;
+/// Resolves once a permit of [`Inserter::with_insert_semaphore`] is acquired,
+/// then holds it until dropped.
+#[cfg(feature = "tokio")]
+type Permit = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Shared notify for [`Inserter::commit_on_notify`], with a listener that is
+/// registered ahead of time so no notification is missed between cuts.
+#[cfg(feature = "tokio")]
+struct FlushNotify {
+    notify: Arc<Notify>,
+    notified: Pin<Box<OwnedNotified>>,
+}
+
+#[cfg(feature = "tokio")]
+impl FlushNotify {
+    fn new(notify: Arc<Notify>) -> Self {
+        let mut notified = Box::pin(Arc::clone(&notify).notified_owned());
+        notified.as_mut().enable();
+        Self { notify, notified }
+    }
+}
+
+/// Acquires a permit of `semaphore` and keeps it inside the returned future,
+/// so that the inserter does not hold a guard type itself.
+#[cfg(feature = "tokio")]
+fn hold_permit(semaphore: Arc<Semaphore>) -> Permit {
+    let mut acquire = Box::pin(semaphore.acquire_owned());
+    let mut permit = None;
+    Box::pin(poll_fn(move |cx| {
+        if permit.is_none() {
+            // A closed semaphore no longer limits anything.
+            permit = Some(ready!(acquire.as_mut().poll(cx)).ok());
+        }
+        Poll::Ready(())
+    }))
+}
+
+/// The async commit callback of a finished flush, still being awaited.
+struct CommitNotice {
+    future: CommitFuture,
+    flushed: Quantities,
+}
+
+struct RowsCallback<T> {
+    snapshot: fn(&[T]) -> Vec<T>,
+    callback: RowsCommitCallback<T>,
+}
+
+/// Retry settings; `copy` keeps the batch for another attempt.
+struct Retry<T> {
+    attempts: u32,
+    copy: CopyBatch<T>,
+}
+
+/// Timeout settings; `copy` keeps the batch to put back if it times out.
+#[cfg(feature = "tokio")]
+struct InsertTimeout<T> {
+    timeout: Duration,
+    copy: CopyBatch<T>,
+}
+
+/// What a failed flush puts back: copies of the batches not yet inserted and
+/// the state the flush cleared.
+struct Restore<T> {
+    batches: VecDeque<Vec<T>>,
+    flushed: Quantities,
+    in_transaction: bool,
+    batch_started_at: Option<Instant>,
+    partial: bool,
+}
+
+/// Batch sequence numbers of [`Inserter::new_idempotent`].
+struct Sequences {
+    /// Sequence number of the batch handed to the insert function next.
+    current: Arc<AtomicU64>,
+    next: u64,
+    /// Numbers of batches a failed flush put back, reused in order by the
+    /// batches that insert their rows again.
+    restored: VecDeque<u64>,
+}
+
+impl Sequences {
+    fn take(&mut self) -> u64 {
+        self.restored.pop_front().unwrap_or_else(|| {
+            self.next += 1;
+            self.next - 1
+        })
+    }
+}
+
+/// One batch of a flush being inserted.
+struct Slot<T, Fut> {
+    future: Pin<Box<Fut>>,
+    /// Copy of the batch for retries.
+    batch: Option<Vec<T>>,
+    attempt: u32,
+    /// Sequence number with [`Inserter::new_idempotent`], kept for retries.
+    sequence: u64,
+    sent: Quantities,
+    /// Succeeded, waiting for earlier batches with
+    /// [`Inserter::with_ordered_flush`].
+    done: bool,
+    #[cfg(feature = "tokio")]
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+/// Moving average behind [`Inserter::ingest_rate`].
+#[derive(Debug, Clone, Copy, Default)]
+struct IngestRate {
+    rate: Option<f64>,
+    sampled_at: Option<Instant>,
+    /// Rows written since `sampled_at`.
+    rows: u64,
+}
+
+impl IngestRate {
+    #[allow(clippy::cast_precision_loss)]
+    fn record(&mut self, now: Instant) {
+        let Some(sampled_at) = self.sampled_at else {
+            self.sampled_at = Some(now);
+            return;
+        };
+        self.rows += 1;
+        let elapsed = now.saturating_duration_since(sampled_at).as_secs_f64();
+        if elapsed == 0.0 {
+            return;
+        }
+        let sample = self.rows as f64 / elapsed;
+        self.rate = Some(self.rate.map_or(sample, |rate| {
+            INGEST_RATE_SMOOTHING.mul_add(sample - rate, rate)
+        }));
+        self.sampled_at = Some(now);
+        self.rows = 0;
+    }
+}
+
+/// Quantities written and not flushed yet. With
+/// [`Inserter::with_drop_check`] dropping it with rows left panics in debug
+/// builds.
+#[derive(Default)]
+struct Pending {
+    quantities: Quantities,
+    check: bool,
+}
+
+impl Deref for Pending {
+    type Target = Quantities;
+
+    fn deref(&self) -> &Quantities {
+        &self.quantities
+    }
+}
+
+impl DerefMut for Pending {
+    fn deref_mut(&mut self) -> &mut Quantities {
+        &mut self.quantities
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if self.check && !thread::panicking() {
+            debug_assert!(
+                self.quantities.rows == 0,
+                "inserter dropped with {} unflushed rows, call end() first",
+                self.quantities.rows
+            );
+        }
+    }
+}
 
-pub struct Inserter<T, F, Fut, E>
+struct InFlight<T, Fut> {
+    /// Batches being inserted, in the order they were started.
+    slots: Vec<Slot<T, Fut>>,
+    restore: Option<Restore<T>>,
+    remaining: VecDeque<Vec<T>>,
+    flushed: Quantities,
+    committed: Quantities,
+    written: u64,
+    snapshot: Option<Vec<T>>,
+    /// Copies sent with [`Inserter::with_tee`] that are still running.
+    tees: Vec<TeeFuture>,
+    started_at: Instant,
+    /// No batch is started before it resolves.
+    #[cfg(feature = "tokio")]
+    permit: Option<Permit>,
+}
+
+/// Progress of [`Inserter::poll_shut_down`], kept by the caller between
+/// polls.
+pub enum ShutDown<Fut> {
+    Flushing,
+    EmptyInsert(Pin<Box<Fut>>),
+    /// Waiting for the detached flusher to insert every queued row.
+    #[cfg(feature = "tokio")]
+    Draining,
+    /// The detached flusher stopped early; this insert fails with the rows
+    /// it lost.
+    #[cfg(feature = "tokio")]
+    LostRows(Pin<Box<Fut>>),
+    Done,
+}
+
+impl<T, Fut> InFlight<T, Fut> {
+    /// Batches still being inserted, leaving out those held by
+    /// [`Inserter::with_ordered_flush`].
+    fn running(&self) -> usize {
+        self.slots.iter().filter(|slot| !slot.done).count()
+    }
+
+    /// Wraps the insert future of every running batch through `map`, for
+    /// [`Inserter::map_err`].
+    fn map_futures<Fut2>(self, mut map: impl FnMut(Pin<Box<Fut>>) -> Fut2) -> InFlight<T, Fut2> {
+        InFlight {
+            slots: self
+                .slots
+                .into_iter()
+                .map(|slot| Slot {
+                    future: Box::pin(map(slot.future)),
+                    batch: slot.batch,
+                    attempt: slot.attempt,
+                    sequence: slot.sequence,
+                    sent: slot.sent,
+                    done: slot.done,
+                    #[cfg(feature = "tokio")]
+                    deadline: slot.deadline,
+                })
+                .collect(),
+            restore: self.restore,
+            remaining: self.remaining,
+            flushed: self.flushed,
+            committed: self.committed,
+            written: self.written,
+            snapshot: self.snapshot,
+            tees: self.tees,
+            started_at: self.started_at,
+            #[cfg(feature = "tokio")]
+            permit: self.permit,
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct Inserter<T, F = NullInsert<T>, Fut = Ready<Result<(), Infallible>>, E = Infallible>
 where
     F: FnMut(Vec<T>) -> Fut,
     Fut: Future<Output = Result<(), E>>,
@@ -17,12 +320,79 @@ where
 {
     insert_fn: F,
     max_rows: u64,
+    min_rows: u64,
+    max_transactions: u64,
+    max_bytes: u64,
+    keep_on_failure: Option<CopyBatch<T>>,
+    extra: Option<Box<dyn ExtraFold<T>>>,
+    retry: Option<Retry<T>>,
+    size_fn: Option<SizeFn<T>>,
+    target_rate: Option<f64>,
+    pressure_threshold: Option<f64>,
+    on_schedule_miss: Option<ScheduleMissCallback>,
+    limit_fn: Option<LimitFn<T>>,
+    memory_pressure: Option<MemoryCheck>,
     buffer: Vec<T>,
+    priority: Vec<T>,
+    buffer_limit: usize,
+    auto_capacity: bool,
+    capacity: usize,
+    overflow_policy: OverflowPolicy,
+    dropped: u64,
+    dedup: Option<Box<dyn DedupFilter<T>>>,
     ticks: Ticks,
-    pending: Quantities,
+    pending: Pending,
     committed: Quantities,
+    flushes: u64,
+    created_at: Instant,
+    last_write_at: Option<Instant>,
+    ingest: IngestRate,
+    idle_timeout: Option<Duration>,
+    batch_started_at: Option<Instant>,
+    max_flush_latency: Duration,
+    retries: u64,
+    commit_every: Option<NonZeroU64>,
+    writes: u64,
+    min_flush_interval: Option<Duration>,
+    last_flush_at: Option<Instant>,
+    final_empty_flush: bool,
     in_transaction: bool,
+    track_transactions: bool,
+    checkpoint: u64,
+    on_checkpoint: Option<CheckpointCallback>,
+    affected: Option<Arc<AtomicU64>>,
+    sequences: Option<Sequences>,
+    submitted: u64,
+    open_from: usize,
+    txn_ids: HashSet<u64>,
     on_commit: Option<CommitCallback>,
+    on_error: Option<ErrorCallback<E>>,
+    on_commit_rows: Option<RowsCallback<T>>,
+    grouper: Option<Grouper<T>>,
+    chunk_size: Option<usize>,
+    commit_per_chunk: bool,
+    flush_concurrency: usize,
+    ordered_flush: bool,
+    max_in_flight: usize,
+    commit_notice: Option<CommitNotice>,
+    on_commit_async: Option<AsyncCommitCallback>,
+    pre_commit: Option<PreCommitCallback<T>>,
+    tee: Option<TeeFn<T>>,
+    observer: Option<Box<dyn Observer>>,
+    finalizer: Option<Finalizer<T>>,
+    buckets: Option<TimeBuckets<T>>,
+    #[cfg(feature = "tokio")]
+    semaphore: Option<Arc<Semaphore>>,
+    #[cfg(feature = "tokio")]
+    insert_timeout: Option<InsertTimeout<T>>,
+    #[cfg(feature = "tokio")]
+    detached: Option<SharedQueue>,
+    #[cfg(feature = "tokio")]
+    flush_notify: Option<FlushNotify>,
+    #[cfg(feature = "period_bias")]
+    sample_rate: Option<f64>,
+    in_flight: Option<InFlight<T, Fut>>,
+    carried: Quantities,
     _phantom: PhantomData<(Fut, E)>,
 }
 
@@ -37,274 +407,5558 @@ where
         Self {
             insert_fn,
             max_rows: u64::MAX,
+            min_rows: 0,
+            max_transactions: u64::MAX,
+            max_bytes: u64::MAX,
+            keep_on_failure: None,
+            extra: None,
+            retry: None,
+            size_fn: None,
+            target_rate: None,
+            pressure_threshold: None,
+            on_schedule_miss: None,
+            limit_fn: None,
+            memory_pressure: None,
             buffer: Vec::new(),
+            priority: Vec::new(),
+            buffer_limit: usize::MAX,
+            auto_capacity: false,
+            capacity: 0,
+            overflow_policy: OverflowPolicy::Grow,
+            dropped: 0,
+            dedup: None,
             ticks: Ticks::new(),
-            pending: Quantities::ZERO,
+            pending: Pending::default(),
             committed: Quantities::ZERO,
+            flushes: 0,
+            created_at: Instant::now(),
+            last_write_at: None,
+            ingest: IngestRate::default(),
+            idle_timeout: None,
+            batch_started_at: None,
+            max_flush_latency: Duration::ZERO,
+            retries: 0,
+            commit_every: None,
+            writes: 0,
+            min_flush_interval: None,
+            last_flush_at: None,
+            final_empty_flush: false,
             in_transaction: false,
+            track_transactions: true,
+            on_checkpoint: None,
+            checkpoint: 0,
+            affected: None,
+            sequences: None,
+            submitted: 0,
+            open_from: 0,
+            txn_ids: HashSet::new(),
             on_commit: None,
+            on_error: None,
+            on_commit_rows: None,
+            grouper: None,
+            chunk_size: None,
+            commit_per_chunk: false,
+            flush_concurrency: 1,
+            ordered_flush: false,
+            max_in_flight: 0,
+            commit_notice: None,
+            on_commit_async: None,
+            pre_commit: None,
+            tee: None,
+            observer: None,
+            finalizer: None,
+            buckets: None,
+            #[cfg(feature = "tokio")]
+            semaphore: None,
+            #[cfg(feature = "tokio")]
+            insert_timeout: None,
+            #[cfg(feature = "tokio")]
+            detached: None,
+            #[cfg(feature = "tokio")]
+            flush_notify: None,
+            #[cfg(feature = "period_bias")]
+            sample_rate: None,
+            in_flight: None,
+            carried: Quantities::ZERO,
             _phantom: PhantomData,
         }
     }
 
+    /// # Panics
+    ///
+    /// Panics if `max_rows` is below the [`with_min_rows`] floor.
+    ///
+    /// [`with_min_rows`]: Self::with_min_rows
     #[must_use]
-    pub const fn with_max_rows(mut self, max_rows: u64) -> Self {
-        self.max_rows = max_rows;
+    pub fn with_max_rows(mut self, max_rows: u64) -> Self {
+        self.set_max_rows(max_rows);
+        self
+    }
+
+    /// Keeps the period from flushing fewer than `min_rows` rows; the tick
+    /// stays due until enough rows arrive. Other limits, [`force_commit`] and
+    /// [`end`] ignore the floor.
+    ///
+    /// [`force_commit`]: Self::force_commit
+    /// [`end`]: Self::end
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_rows` exceeds the row limit.
+    #[must_use]
+    pub fn with_min_rows(mut self, min_rows: u64) -> Self {
+        assert!(
+            min_rows <= self.max_rows,
+            "min rows {min_rows} exceed max rows {}",
+            self.max_rows
+        );
+        self.min_rows = min_rows;
         self
     }
 
     #[must_use]
-    pub const fn with_period(mut self, period: Duration) -> Self {
+    pub fn with_period(mut self, period: Duration) -> Self {
+        self.target_rate = None;
         self.ticks = self.ticks.with_period(period);
         self
     }
 
-    #[cfg(feature = "period_bias")]
+    /// Makes [`commit`] flush once rows are buffered and nothing has been
+    /// written for `idle_timeout`, so a trickle that stops is not held back
+    /// until the period.
+    ///
+    /// [`commit`]: Self::commit
     #[must_use]
-    pub fn with_period_bias(mut self, bias: f64) -> Self {
-        self.ticks = self.ticks.with_bias(bias);
+    pub const fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
         self
     }
 
+    /// Registers a callback told how late a reschedule was whenever a
+    /// [`Schedule::FixedRate`] tick was missed entirely, e.g. because a flush
+    /// ran longer than the period.
     #[must_use]
-    pub fn with_commit_callback<C>(mut self, callback: C) -> Self
+    pub fn with_schedule_miss_callback<C>(mut self, callback: C) -> Self
     where
-        C: FnMut(&Quantities) + Send + 'static,
+        C: FnMut(Duration) + Send + 'static,
     {
-        self.on_commit = Some(Box::new(callback));
+        self.on_schedule_miss = Some(Box::new(callback));
         self
     }
 
+    /// Chooses whether period ticks follow the last flush
+    /// ([`Schedule::FixedDelay`], the default) or a fixed cadence
+    /// ([`Schedule::FixedRate`]).
     #[must_use]
-    pub const fn pending(&self) -> &Quantities {
-        &self.pending
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.ticks = self.ticks.with_schedule(schedule);
+        self
     }
 
+    /// Flushes once `max_transactions` transactions are pending. A write that
+    /// opens a transaction counts toward the limit immediately.
     #[must_use]
-    pub fn time_left(&self) -> Option<Duration> {
-        self.ticks.time_left()
+    pub const fn with_max_transactions(mut self, max_transactions: u64) -> Self {
+        self.max_transactions = max_transactions;
+        self
     }
 
-    fn limits_reached(&self) -> bool {
-        self.pending.rows >= self.max_rows || self.ticks.reached()
+    /// Flushes once the estimated size of the buffered rows reaches
+    /// `max_bytes`. Sizes come from [`with_size_fn`]; without one this limit
+    /// never triggers.
+    ///
+    /// [`with_size_fn`]: Self::with_size_fn
+    #[must_use]
+    pub const fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
     }
 
-    fn start_if_needed(&mut self) {
-        self.ticks.start();
+    /// Estimates the size of each written row, accumulated in
+    /// [`Quantities::bytes`] and checked against [`with_max_bytes`].
+    ///
+    /// [`with_max_bytes`]: Self::with_max_bytes
+    #[must_use]
+    pub fn with_size_fn<S>(mut self, size_fn: S) -> Self
+    where
+        S: Fn(&T) -> usize + Send + 'static,
+    {
+        self.size_fn = Some(Box::new(size_fn));
+        self
     }
 
-    pub fn write_owned(&mut self, item: T) {
-        self.start_if_needed();
-
-        self.buffer.push(item);
-        self.pending.rows += 1;
+    /// Accumulates a custom counter over the rows of each flush, for
+    /// sink-specific metrics that don't belong in [`Quantities`].
+    ///
+    /// The flushing methods of the returned [`ExtraInserter`] fold the rows
+    /// they flushed into a fresh `X::default()` and return it along with the
+    /// flushed [`Quantities`]. Rows of failed flushes are not counted.
+    #[must_use]
+    pub fn with_extra_counter<X, G>(mut self, fold: G) -> ExtraInserter<T, F, Fut, E, X>
+    where
+        X: Default + Clone + Send + 'static,
+        G: FnMut(&mut X, &T) + Send + 'static,
+    {
+        let (folder, counter) = extra_counter(fold);
+        self.extra = Some(folder);
+        ExtraInserter::new(self, counter)
+    }
 
-        if !self.in_transaction {
-            self.pending.transactions += 1;
-            self.in_transaction = true;
+    /// Applies every limit in `limits` at once.
+    #[must_use]
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self = self
+            .with_max_rows(limits.max_rows)
+            .with_max_bytes(limits.max_bytes)
+            .with_max_transactions(limits.max_transactions);
+        if let Some(period) = limits.period {
+            self = self.with_period(period);
         }
+        self.idle_timeout = limits.idle_timeout;
+        self
     }
 
-    async fn flush(&mut self) -> Result<Quantities, InserterError<E>> {
-        if self.buffer.is_empty() {
-            return Ok(Quantities::ZERO);
-        }
+    /// Derives the period from a target throughput as `max_rows / rows_per_sec`.
+    ///
+    /// The period is recomputed whenever the row limit changes through
+    /// [`with_max_rows`] or [`set_max_rows`], and is only derived while
+    /// `max_rows` is finite. A later [`with_period`] replaces the derived period.
+    ///
+    /// [`with_max_rows`]: Self::with_max_rows
+    /// [`set_max_rows`]: Self::set_max_rows
+    /// [`with_period`]: Self::with_period
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rows_per_sec` is not a positive finite number.
+    #[must_use]
+    pub fn with_target_rate(mut self, rows_per_sec: f64) -> Self {
+        assert!(
+            rows_per_sec.is_finite() && rows_per_sec > 0.0,
+            "target rate must be a positive finite number"
+        );
+        self.target_rate = Some(rows_per_sec);
+        self.apply_target_rate();
+        self
+    }
 
-        let batch = std::mem::take(&mut self.buffer);
-        let flushed = self.pending;
+    /// # Panics
+    ///
+    /// Panics if `max_rows` is below the [`with_min_rows`] floor.
+    ///
+    /// [`with_min_rows`]: Self::with_min_rows
+    pub fn set_max_rows(&mut self, max_rows: u64) {
+        assert!(
+            max_rows >= self.min_rows,
+            "max rows {max_rows} below min rows {}",
+            self.min_rows
+        );
+        self.max_rows = max_rows;
+        self.apply_target_rate();
+        self.reserve_capacity();
+    }
 
-        (self.insert_fn)(batch).await.map_err(InserterError::new)?;
+    /// Preallocates the buffer for `max_rows` items, capped at about a
+    /// million, now and after every flush that empties it.
+    #[must_use]
+    pub fn with_auto_capacity(mut self, enabled: bool) -> Self {
+        self.auto_capacity = enabled;
+        self.reserve_capacity();
+        self
+    }
 
-        self.committed.rows += flushed.rows;
-        self.committed.transactions += flushed.transactions;
-        self.pending = Quantities::ZERO;
-        self.in_transaction = false;
+    /// Preallocates the buffer for `capacity` items, now and after every
+    /// flush that empties it, so the buffer does not regrow from zero on
+    /// every cycle.
+    #[must_use]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self.reserve_capacity();
+        self
+    }
 
-        if let Some(ref mut callback) = self.on_commit {
-            callback(&flushed);
+    fn reserve_capacity(&mut self) {
+        let mut capacity = self.capacity;
+        if self.auto_capacity && self.max_rows != u64::MAX {
+            capacity = capacity.max(
+                usize::try_from(self.max_rows)
+                    .unwrap_or(usize::MAX)
+                    .min(MAX_AUTO_CAPACITY),
+            );
         }
-
-        Ok(flushed)
+        self.buffer
+            .reserve_exact(capacity.saturating_sub(self.buffer.len()));
     }
 
-    /// Checks limits and flushes if reached.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the insert function fails.
-    pub async fn commit(&mut self) -> Result<Quantities, InserterError<E>> {
-        if !self.limits_reached() {
-            self.in_transaction = false;
-            return Ok(Quantities::ZERO);
+    #[allow(clippy::cast_precision_loss)]
+    fn apply_target_rate(&mut self) {
+        if let Some(rows_per_sec) = self.target_rate {
+            if self.max_rows != u64::MAX {
+                let secs = self.max_rows as f64 / rows_per_sec;
+                self.ticks.set_period(Duration::from_secs_f64(secs));
+            }
         }
+    }
 
-        self.force_commit().await
+    #[cfg(feature = "period_bias")]
+    #[must_use]
+    pub fn with_period_bias(mut self, bias: f64) -> Self {
+        self.ticks = self.ticks.with_bias(bias);
+        self
     }
 
-    /// Flushes unconditionally, regardless of limits.
+    /// Seeds the RNG behind the period bias, making biased periods
+    /// reproducible.
+    #[cfg(feature = "period_bias")]
+    #[must_use]
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.ticks = self.ticks.with_seed(seed);
+        self
+    }
+
+    /// Shorthand for [`with_period_bias`] with an RNG seeded from `seed`.
     ///
-    /// # Errors
+    /// [`with_period_bias`]: Self::with_period_bias
+    #[cfg(feature = "period_bias")]
+    #[must_use]
+    pub fn with_seeded_bias(mut self, bias: f64, seed: u64) -> Self {
+        self.ticks = self.ticks.with_seeded_bias(bias, seed);
+        self
+    }
+
+    /// Keeps each written row with probability `rate`, dropping the rest.
+    /// Sampled-out rows are counted in [`dropped`](Self::dropped). Samples are
+    /// drawn from the same RNG as the period bias, so
+    /// [`with_rng_seed`](Self::with_rng_seed) makes them reproducible.
     ///
-    /// Returns an error if the insert function fails.
-    pub async fn force_commit(&mut self) -> Result<Quantities, InserterError<E>> {
-        let result = self.flush().await?;
-        self.ticks.reschedule();
-        Ok(result)
+    /// # Panics
+    ///
+    /// Panics if `rate` is not within `0.0..=1.0`.
+    #[cfg(feature = "period_bias")]
+    #[must_use]
+    pub fn with_sampling(mut self, rate: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&rate),
+            "sampling rate must be within 0.0..=1.0"
+        );
+        self.sample_rate = Some(rate);
+        self
     }
 
-    /// Consumes the inserter and flushes remaining buffered items.
+    /// Groups rows into fixed-width time buckets by `timestamp` and flushes each
+    /// bucket as its own insert.
     ///
-    /// # Errors
+    /// Buckets are aligned to the timestamp of the first row written. A bucket
+    /// closes once the clock passes its end; [`commit`] then flushes closed
+    /// buckets and keeps rows of open buckets buffered, unless another limit
+    /// is reached, in which case every bucket is flushed. [`force_commit`] and
+    /// [`end`] always flush every bucket.
     ///
-    /// Returns an error if the insert function fails.
-    pub async fn end(mut self) -> Result<Quantities, InserterError<E>> {
-        self.flush().await?;
-        Ok(self.committed)
+    /// When a flush sends only part of the buffer, all pending transactions but
+    /// one are reported with it; the remaining one is reported by a later flush.
+    ///
+    /// [`commit`]: Self::commit
+    /// [`force_commit`]: Self::force_commit
+    /// [`end`]: Self::end
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket` is zero.
+    #[must_use]
+    pub fn with_time_bucketing<TS>(mut self, bucket: Duration, timestamp: TS) -> Self
+    where
+        TS: Fn(&T) -> Instant + Send + 'static,
+    {
+        assert!(!bucket.is_zero(), "bucket width must be non-zero");
+        self.buckets = Some(TimeBuckets::new(bucket, Box::new(timestamp)));
+        self
     }
-}
 
-impl<T, F, Fut, E> Inserter<T, F, Fut, E>
-where
-    T: Clone,
-    F: FnMut(Vec<T>) -> Fut,
-    Fut: Future<Output = Result<(), E>>,
-    E: Error,
-{
-    pub fn write(&mut self, item: &T) {
-        self.write_owned(item.clone());
+    /// Makes [`commit`] flush whenever the total number of accepted writes is
+    /// a multiple of `n`, independent of the row limit. Zero disables it.
+    ///
+    /// [`commit`]: Self::commit
+    #[must_use]
+    pub const fn with_commit_every(mut self, n: u64) -> Self {
+        self.commit_every = NonZeroU64::new(n);
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::cell::RefCell;
-    use std::io;
-    use std::rc::Rc;
-    use std::sync::{Arc, Mutex};
+    /// Makes [`commit`] flush once [`pressure`] reaches `threshold`, even if
+    /// no single limit is reached yet.
+    ///
+    /// [`commit`]: Self::commit
+    /// [`pressure`]: Self::pressure
+    #[must_use]
+    pub const fn with_pressure_threshold(mut self, threshold: f64) -> Self {
+        self.pressure_threshold = Some(threshold);
+        self
+    }
 
-    #[derive(Clone, Debug, PartialEq)]
-    struct TestRow {
-        id: u64,
+    /// Adds a custom limit: [`commit`] also flushes when `limit` returns `true`
+    /// for the pending quantities and the buffered items. It is only called
+    /// when checking limits, not on writes.
+    ///
+    /// [`commit`]: Self::commit
+    #[must_use]
+    pub fn with_limit_fn<L>(mut self, limit: L) -> Self
+    where
+        L: FnMut(&Quantities, &[T]) -> bool + Send + 'static,
+    {
+        self.limit_fn = Some(Box::new(limit));
+        self
     }
 
-    #[test]
-    fn test_basic_insert() {
-        pollster::block_on(async {
-            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
-            let inserted_clone = Rc::clone(&inserted);
+    /// Makes [`commit`] flush buffered rows whenever `check` reports memory
+    /// pressure, e.g. from cgroup memory usage, regardless of other limits.
+    ///
+    /// [`commit`]: Self::commit
+    #[must_use]
+    pub fn with_memory_pressure_flush<C>(mut self, check: C) -> Self
+    where
+        C: Fn() -> bool + Send + 'static,
+    {
+        self.memory_pressure = Some(Box::new(check));
+        self
+    }
 
-            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
-                let inserted = Rc::clone(&inserted_clone);
-                async move {
-                    inserted.borrow_mut().push(batch);
-                    Ok::<_, io::Error>(())
-                }
-            })
-            .with_max_rows(2);
+    /// Keeps [`commit`] from flushing again until `interval` has passed since
+    /// the previous flush; items keep buffering meanwhile. [`force_commit`],
+    /// [`end`] and priority items bypass the throttle.
+    ///
+    /// [`commit`]: Self::commit
+    /// [`force_commit`]: Self::force_commit
+    /// [`end`]: Self::end
+    #[must_use]
+    pub const fn with_min_flush_interval(mut self, interval: Duration) -> Self {
+        self.min_flush_interval = Some(interval);
+        self
+    }
 
-            inserter.write(&TestRow { id: 1 });
-            inserter.write(&TestRow { id: 2 });
+    /// Turns transaction counting off when `enabled` is `false`, skipping its
+    /// bookkeeping on every write. Transaction counts then stay zero and
+    /// [`flush_complete_transactions`] flushes everything.
+    ///
+    /// [`flush_complete_transactions`]: Self::flush_complete_transactions
+    #[must_use]
+    pub const fn with_transaction_tracking(mut self, enabled: bool) -> Self {
+        self.track_transactions = enabled;
+        self
+    }
 
-            let stats = inserter.commit().await.unwrap();
-            assert_eq!(stats.rows, 2);
+    /// Makes [`end`] call the insert function with an empty batch when the
+    /// buffer is already empty, e.g. to let the sink close its stream.
+    ///
+    /// [`end`]: Self::end
+    #[must_use]
+    pub const fn with_final_empty_flush(mut self, enabled: bool) -> Self {
+        self.final_empty_flush = enabled;
+        self
+    }
 
-            let batches = inserted.borrow();
-            assert_eq!(batches.len(), 1);
-            assert_eq!(batches[0].len(), 2);
-        });
+    /// Caps the number of buffered items; see [`with_overflow_policy`] for
+    /// what happens to writes beyond the cap.
+    ///
+    /// [`with_overflow_policy`]: Self::with_overflow_policy
+    #[must_use]
+    pub const fn with_buffer_limit(mut self, limit: usize) -> Self {
+        self.buffer_limit = limit;
+        self
     }
 
-    #[test]
-    fn test_force_commit() {
-        pollster::block_on(async {
-            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
-            let inserted_clone = Rc::clone(&inserted);
+    #[must_use]
+    pub const fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Drops writes whose key was already seen, across flushes.
+    ///
+    /// Keys are remembered for `ttl` after the item was accepted, and at most
+    /// `capacity` keys are kept, evicting the oldest first. Suppressed writes
+    /// are counted in [`dropped`](Self::dropped). The TTL is measured with the
+    /// configured clock.
+    #[must_use]
+    pub fn with_cross_batch_dedup<K, KF>(
+        mut self,
+        key_fn: KF,
+        capacity: usize,
+        ttl: Duration,
+    ) -> Self
+    where
+        K: Hash + Eq + Clone + Send + 'static,
+        KF: FnMut(&T) -> K + Send + 'static,
+    {
+        self.dedup = Some(Box::new(CrossBatchDedup::new(key_fn, capacity, ttl)));
+        self
+    }
+
+    /// Reads time for all time-based limits from `clock` instead of
+    /// [`Instant::now`](std::time::Instant::now).
+    #[must_use]
+    pub fn with_clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        self.ticks = self.ticks.with_clock(Arc::new(clock));
+        self.created_at = self.ticks.now();
+        self
+    }
+
+    /// Holds a permit from `semaphore` for the duration of every flush, so
+    /// inserters sharing it run at most as many flushes at once as it has
+    /// permits. The insert function is not called until the permit is
+    /// acquired.
+    #[cfg(feature = "tokio")]
+    #[must_use]
+    pub fn with_insert_semaphore(mut self, semaphore: Arc<Semaphore>) -> Self {
+        self.semaphore = Some(semaphore);
+        self
+    }
+
+    /// Registers `notify` to cut batches with [`commit_on_notify`]. Sharing one
+    /// notify across inserters and calling [`Notify::notify_waiters`] flushes
+    /// all of them at the same logical point, e.g. a snapshot boundary.
+    ///
+    /// [`commit_on_notify`]: Self::commit_on_notify
+    #[cfg(feature = "tokio")]
+    #[must_use]
+    pub fn with_flush_notify(mut self, notify: Arc<Notify>) -> Self {
+        self.flush_notify = Some(FlushNotify::new(notify));
+        self
+    }
+
+    /// Computes time-based flush deadlines with `scheduler`, replacing the
+    /// period (and its bias) for that purpose.
+    #[must_use]
+    pub fn with_scheduler<S>(mut self, scheduler: S) -> Self
+    where
+        S: Scheduler + 'static,
+    {
+        self.ticks = self.ticks.with_scheduler(Arc::new(scheduler));
+        self
+    }
+
+    #[must_use]
+    pub fn with_commit_callback<C>(mut self, callback: C) -> Self
+    where
+        C: FnMut(&Quantities) + Send + 'static,
+    {
+        self.set_commit_callback(callback);
+        self
+    }
+
+    /// Registers a callback receiving the error and the quantities that
+    /// failed to commit whenever a flush fails, after any retries. The error
+    /// is still returned by the flush. Timeouts from `with_insert_timeout`
+    /// carry no error of the insert function and are not reported.
+    #[must_use]
+    pub fn with_error_callback<C>(mut self, callback: C) -> Self
+    where
+        C: FnMut(&E, &Quantities) + Send + 'static,
+    {
+        self.on_error = Some(Box::new(callback));
+        self
+    }
+
+    /// Registers an async callback awaited after every successful flush, once
+    /// the committed counters are updated and the commit callback has run.
+    /// The flush only completes when the callback's future does.
+    #[must_use]
+    pub fn with_async_commit_callback<C, CFut>(mut self, mut callback: C) -> Self
+    where
+        C: FnMut(&Quantities) -> CFut + Send + 'static,
+        CFut: Future<Output = ()> + Send + 'static,
+    {
+        self.on_commit_async = Some(Box::new(move |flushed| Box::pin(callback(flushed))));
+        self
+    }
+
+    /// Replaces the commit callback at runtime.
+    pub fn set_commit_callback<C>(&mut self, callback: C)
+    where
+        C: FnMut(&Quantities) + Send + 'static,
+    {
+        self.on_commit = Some(Box::new(callback));
+    }
+
+    /// Registers a callback run right before each insert with the batch and
+    /// the quantities being flushed. Returning `false` cancels the whole
+    /// flush, leaving its rows buffered and the pending counters unchanged;
+    /// cancelled priority items stay at the front of the buffer but no longer
+    /// force a flush.
+    ///
+    /// A flush runs the pre-commit callback (which may cancel it), then the
+    /// insert function, then updates the committed counters, then runs the
+    /// commit callback.
+    #[must_use]
+    pub fn with_pre_commit_callback<C>(mut self, callback: C) -> Self
+    where
+        C: FnMut(&[T], &Quantities) -> bool + Send + 'static,
+    {
+        self.pre_commit = Some(Box::new(callback));
+        self
+    }
+
+    /// Sends a best-effort copy of every batch to `tee_fn`, e.g. an audit
+    /// sink, without requiring `T: Clone`.
+    ///
+    /// `tee_fn` sees each batch before the insert function takes it and runs
+    /// concurrently with the insert; the flush completes once both are done.
+    /// Its errors go to the [observer](Self::with_observer) and never fail
+    /// the flush. Copies still running when a flush fails are dropped.
+    #[must_use]
+    pub fn with_tee<Te, TeFut, TeE>(mut self, mut tee_fn: Te) -> Self
+    where
+        Te: FnMut(&[T]) -> TeFut + Send + 'static,
+        TeFut: Future<Output = Result<(), TeE>> + Send + 'static,
+        TeE: Error + Send + Sync + 'static,
+    {
+        self.tee = Some(Box::new(move |batch| {
+            let copy = tee_fn(batch);
+            Box::pin(async move { copy.await.map_err(Into::into) })
+        }));
+        self
+    }
+
+    /// Registers an observer told about events that do not fail a flush,
+    /// such as failed [tee](Self::with_tee) copies.
+    #[must_use]
+    pub fn with_observer<O>(mut self, observer: O) -> Self
+    where
+        O: Observer + 'static,
+    {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Registers a callback receiving the new [`checkpoint`] after every
+    /// successful flush, e.g. to commit a source offset upstream. It runs
+    /// right after the commit callback of the same flush.
+    ///
+    /// The checkpoint has a callback of its own rather than being passed to
+    /// the commit callback, so that existing `FnMut(&Quantities)` commit
+    /// callbacks keep compiling. With [`with_commit_per_chunk`] the commit
+    /// callback also runs for every chunk, while the checkpoint only moves
+    /// once the whole flush succeeded.
+    ///
+    /// [`checkpoint`]: Self::checkpoint
+    /// [`with_commit_per_chunk`]: Self::with_commit_per_chunk
+    #[must_use]
+    pub fn with_checkpoint_callback<C>(mut self, callback: C) -> Self
+    where
+        C: FnMut(u64) + Send + 'static,
+    {
+        self.on_checkpoint = Some(Box::new(callback));
+        self
+    }
+
+    /// Removes the commit callback, if any.
+    pub fn clear_commit_callback(&mut self) {
+        self.on_commit = None;
+    }
+
+    /// Calls `finalizer` on every batch right before it is handed to the
+    /// insert function, e.g. to append a footer row. Rows it adds or removes
+    /// are reflected in the flushed quantities.
+    #[must_use]
+    pub fn with_batch_finalizer<Fin>(mut self, finalizer: Fin) -> Self
+    where
+        Fin: FnMut(&mut Vec<T>) + Send + 'static,
+    {
+        self.finalizer = Some(Box::new(finalizer));
+        self
+    }
+
+    /// Groups buffered items by `key_fn` at flush time, calling the insert
+    /// function once per group in ascending key order.
+    ///
+    /// All groups share a single buffer and limits apply to it as a whole; the
+    /// flushed quantities are aggregated across groups. If an insert fails, the
+    /// remaining groups of that flush are not sent.
+    #[must_use]
+    pub fn with_group_key<K, KF>(mut self, mut key_fn: KF) -> Self
+    where
+        K: Ord,
+        KF: FnMut(&T) -> K + Send + 'static,
+    {
+        self.grouper = Some(Box::new(move |batch| {
+            let mut groups: BTreeMap<K, Vec<T>> = BTreeMap::new();
+            for item in batch {
+                groups.entry(key_fn(&item)).or_default().push(item);
+            }
+            groups.into_values().collect()
+        }));
+        self
+    }
+
+    /// Splits every flushed batch into chunks of at most `chunk_size` rows,
+    /// inserted one after another, e.g. to stay below a per-statement limit.
+    ///
+    /// Committed quantities advance with every successful chunk. If a chunk
+    /// fails, the chunks not sent yet are put back into the buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    #[must_use]
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        self.chunk_size = Some(chunk_size);
+        self
+    }
+
+    /// Fires the commit callback once per chunk set with
+    /// [`with_chunk_size`](Self::with_chunk_size), with that chunk's
+    /// quantities, instead of once per flush with the total. Transactions
+    /// are counted with the last chunk.
+    #[must_use]
+    pub const fn with_commit_per_chunk(mut self, enabled: bool) -> Self {
+        self.commit_per_chunk = enabled;
+        self
+    }
+
+    /// Inserts up to `concurrency` batches of a flush at once, e.g. the chunks
+    /// of [`with_chunk_size`](Self::with_chunk_size) or the groups of
+    /// [`with_group_key`](Self::with_group_key). The insert function is still
+    /// called one batch at a time, so it needs no `Clone`; only the futures it
+    /// returns run concurrently.
+    ///
+    /// If a batch fails, the others still running are dropped and the error
+    /// is returned. Chunks that already succeeded stay committed, cancelled
+    /// ones are only put back with
+    /// [`with_restore_on_failure`](Self::with_restore_on_failure).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is zero.
+    #[must_use]
+    pub fn with_flush_concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "flush concurrency must be positive");
+        self.flush_concurrency = concurrency;
+        self
+    }
+
+    /// With [`with_flush_concurrency`](Self::with_flush_concurrency), applies
+    /// the effects of finished batches, i.e. committed counters and per-chunk
+    /// commit callbacks, in the order the batches were started instead of the
+    /// order they finish.
+    ///
+    /// Batches are still inserted concurrently. One that finishes before an
+    /// earlier batch is held, along with its insert future, until every batch
+    /// before it finished. Held batches do not count against the concurrency,
+    /// so a slow batch can keep up to the rest of its flush in memory.
+    #[must_use]
+    pub const fn with_ordered_flush(mut self, ordered: bool) -> Self {
+        self.ordered_flush = ordered;
+        self
+    }
+
+    /// Panics in debug builds when the inserter is dropped with rows still
+    /// buffered, i.e. without [`end`](Self::end) or a final flush, to catch
+    /// rows silently lost on an early return. Release builds drop them as
+    /// before.
+    ///
+    /// `Drop` cannot run the asynchronous insert function, so the rows are
+    /// never flushed from here. Rows of a flush still in flight are not
+    /// checked, and neither is an inserter dropped while already panicking.
+    #[must_use]
+    pub const fn with_drop_check(mut self, check: bool) -> Self {
+        self.pending.check = check;
+        self
+    }
+
+    /// Converts the error type of the insert function through `map`, so that
+    /// `commit` and friends return `InserterError<E2>`.
+    ///
+    /// Buffered items, counters and configuration carry over unchanged,
+    /// except for the error callback, which is dropped since it takes the old
+    /// error type.
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn map_err<E2, G>(
+        self,
+        map: G,
+    ) -> Inserter<T, impl FnMut(Vec<T>) -> MapErr<Fut, G>, MapErr<Fut, G>, E2>
+    where
+        G: Fn(E) -> E2,
+        E2: Error,
+    {
+        let mut insert_fn = self.insert_fn;
+        let map = Arc::new(map);
+        let in_flight = self.in_flight.map(|in_flight| {
+            in_flight.map_futures(|future| MapErr::from_pinned(future, Arc::clone(&map)))
+        });
+
+        Inserter {
+            insert_fn: move |batch| MapErr::new(insert_fn(batch), Arc::clone(&map)),
+            max_rows: self.max_rows,
+            min_rows: self.min_rows,
+            max_transactions: self.max_transactions,
+            max_bytes: self.max_bytes,
+            keep_on_failure: self.keep_on_failure,
+            extra: self.extra,
+            retry: self.retry,
+            size_fn: self.size_fn,
+            target_rate: self.target_rate,
+            pressure_threshold: self.pressure_threshold,
+            on_schedule_miss: self.on_schedule_miss,
+            limit_fn: self.limit_fn,
+            memory_pressure: self.memory_pressure,
+            buffer: self.buffer,
+            priority: self.priority,
+            buffer_limit: self.buffer_limit,
+            auto_capacity: self.auto_capacity,
+            capacity: self.capacity,
+            overflow_policy: self.overflow_policy,
+            dropped: self.dropped,
+            dedup: self.dedup,
+            ticks: self.ticks,
+            pending: self.pending,
+            committed: self.committed,
+            flushes: self.flushes,
+            created_at: self.created_at,
+            last_write_at: self.last_write_at,
+            ingest: self.ingest,
+            idle_timeout: self.idle_timeout,
+            batch_started_at: self.batch_started_at,
+            max_flush_latency: self.max_flush_latency,
+            retries: self.retries,
+            commit_every: self.commit_every,
+            writes: self.writes,
+            min_flush_interval: self.min_flush_interval,
+            last_flush_at: self.last_flush_at,
+            final_empty_flush: self.final_empty_flush,
+            in_transaction: self.in_transaction,
+            track_transactions: self.track_transactions,
+            on_checkpoint: self.on_checkpoint,
+            checkpoint: self.checkpoint,
+            affected: self.affected,
+            sequences: self.sequences,
+            submitted: self.submitted,
+            open_from: self.open_from,
+            txn_ids: self.txn_ids,
+            on_commit: self.on_commit,
+            on_error: None,
+            on_commit_rows: self.on_commit_rows,
+            grouper: self.grouper,
+            chunk_size: self.chunk_size,
+            commit_per_chunk: self.commit_per_chunk,
+            flush_concurrency: self.flush_concurrency,
+            ordered_flush: self.ordered_flush,
+            max_in_flight: self.max_in_flight,
+            commit_notice: self.commit_notice,
+            on_commit_async: self.on_commit_async,
+            pre_commit: self.pre_commit,
+            tee: self.tee,
+            observer: self.observer,
+            finalizer: self.finalizer,
+            buckets: self.buckets,
+            #[cfg(feature = "tokio")]
+            semaphore: self.semaphore,
+            #[cfg(feature = "tokio")]
+            insert_timeout: self.insert_timeout,
+            #[cfg(feature = "tokio")]
+            detached: self.detached,
+            #[cfg(feature = "tokio")]
+            flush_notify: self.flush_notify,
+            #[cfg(feature = "period_bias")]
+            sample_rate: self.sample_rate,
+            in_flight,
+            carried: self.carried,
+            _phantom: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub const fn pending(&self) -> &Quantities {
+        &self.pending.quantities
+    }
+
+    /// Quantities committed so far, as returned by [`end`].
+    ///
+    /// [`end`]: Self::end
+    #[must_use]
+    pub const fn committed(&self) -> &Quantities {
+        &self.committed
+    }
+
+    /// Returns the committed quantities and resets them to zero, e.g. to report
+    /// deltas once per interval. [`end`] and [`finish`] then only count what
+    /// was committed after the last reset.
+    ///
+    /// [`end`]: Self::end
+    /// [`finish`]: Self::finish
+    pub const fn reset_committed(&mut self) -> Quantities {
+        std::mem::replace(&mut self.committed, Quantities::ZERO)
+    }
+
+    /// Drops everything buffered, including priority items, without calling
+    /// the insert function, and returns the dropped quantities. No callbacks
+    /// fire, the period timer is left alone and a flush already in flight is
+    /// not affected. Discarded rows are not counted in
+    /// [`dropped`](Self::dropped).
+    pub fn discard(&mut self) -> Quantities {
+        self.buffer.clear();
+        self.priority.clear();
+        if let Some(ref mut buckets) = self.buckets {
+            buckets.clear();
+        }
+        self.open_from = 0;
+        self.txn_ids.clear();
+        self.in_transaction = false;
+        self.batch_started_at = None;
+        std::mem::replace(&mut self.pending, Quantities::ZERO)
+    }
+
+    /// The regular buffered items, in write order, e.g. to inspect a batch
+    /// before deciding to commit. Priority items are not included.
+    #[must_use]
+    pub fn buffered(&self) -> &[T] {
+        &self.buffer
+    }
+
+    /// Mutable access to the regular buffered items, in write order, for
+    /// point edits before they are flushed. Priority items are not included.
+    /// Counters, including estimated bytes, are not recomputed.
+    pub fn pending_items_mut(&mut self) -> &mut [T] {
+        &mut self.buffer
+    }
+
+    /// Number of items buffered right now, including priority items.
+    #[must_use]
+    pub fn buffer_len(&self) -> usize {
+        self.buffer.len() + self.priority.len()
+    }
+
+    #[must_use]
+    pub fn is_buffer_empty(&self) -> bool {
+        self.buffer.is_empty() && self.priority.is_empty()
+    }
+
+    /// Number of items discarded by the overflow policy, deduplication or
+    /// sampling.
+    #[must_use]
+    pub const fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Number of keys remembered by cross-batch deduplication; zero if it is
+    /// not configured. Expired keys are evicted lazily on the next write.
+    #[must_use]
+    pub fn dedup_len(&self) -> usize {
+        self.dedup.as_ref().map_or(0, |dedup| dedup.len())
+    }
+
+    /// Forgets every key seen by cross-batch deduplication, so previously
+    /// seen keys are accepted again.
+    pub fn clear_dedup(&mut self) {
+        if let Some(dedup) = self.dedup.as_mut() {
+            dedup.clear();
+        }
+    }
+
+    /// Estimated heap footprint of the buffered items in bytes.
+    ///
+    /// This counts the allocated buffer capacity times `size_of::<T>()`, plus
+    /// the bytes of the buffered items from [`with_size_fn`] if one is set to
+    /// account for heap memory owned by the items themselves.
+    ///
+    /// [`with_size_fn`]: Self::with_size_fn
+    #[must_use]
+    pub fn buffer_memory_estimate(&self) -> usize {
+        let slots = (self.buffer.capacity() + self.priority.capacity()) * size_of::<T>();
+        if self.size_fn.is_none() {
+            return slots;
+        }
+        slots.saturating_add(usize::try_from(self.pending.bytes).unwrap_or(usize::MAX))
+    }
+
+    /// Rows handed to the insert function by successful flushes. Differs
+    /// from the committed rows only for inserters built with
+    /// [`new_counting`](Inserter::new_counting).
+    #[must_use]
+    pub const fn submitted_rows(&self) -> u64 {
+        self.submitted
+    }
+
+    /// Number of written rows flushed successfully so far, a monotonic
+    /// position for source offset tracking. Unlike the committed rows it
+    /// ignores affected-row counts and rows added by a batch finalizer; rows
+    /// dropped before flushing are not included.
+    #[must_use]
+    pub const fn checkpoint(&self) -> u64 {
+        self.checkpoint
+    }
+
+    /// Number of successful flushes so far. Commits with nothing buffered
+    /// are not counted.
+    #[must_use]
+    pub const fn flush_count(&self) -> u64 {
+        self.flushes
+    }
+
+    /// Number of batches currently being inserted, at most the
+    /// [`with_flush_concurrency`](Self::with_flush_concurrency) limit.
+    #[must_use]
+    pub fn flush_in_flight(&self) -> usize {
+        self.in_flight.as_ref().map_or(0, InFlight::running)
+    }
+
+    /// Highest [`flush_in_flight`](Self::flush_in_flight) seen so far. A
+    /// value at the concurrency limit means flushes were waiting on it.
+    #[must_use]
+    pub const fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    #[must_use]
+    pub const fn max_rows(&self) -> u64 {
+        self.max_rows
+    }
+
+    #[must_use]
+    pub const fn max_transactions(&self) -> u64 {
+        self.max_transactions
+    }
+
+    #[must_use]
+    pub const fn period(&self) -> Option<Duration> {
+        self.ticks.period()
+    }
+
+    #[must_use]
+    pub const fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    #[must_use]
+    pub const fn has_period(&self) -> bool {
+        self.ticks.has_period()
+    }
+
+    #[must_use]
+    pub fn time_left(&self) -> Option<Duration> {
+        self.ticks.time_left()
+    }
+
+    /// When the next period tick is due, for comparing deadlines across
+    /// inserters. `None` until the first write or without a period.
+    #[must_use]
+    pub const fn next_flush_at(&self) -> Option<Instant> {
+        self.ticks.next_at()
+    }
+
+    /// How much of the current period has elapsed, in `[0, 1]`, or `None`
+    /// without a period.
+    #[must_use]
+    pub fn period_elapsed_fraction(&self) -> Option<f64> {
+        self.ticks.period_elapsed_fraction()
+    }
+
+    /// Rows still to be written before the row limit is reached, or `None`
+    /// without a row limit.
+    #[must_use]
+    pub const fn rows_until_flush(&self) -> Option<u64> {
+        if self.max_rows == u64::MAX {
+            None
+        } else {
+            Some(self.max_rows.saturating_sub(self.pending.quantities.rows))
+        }
+    }
+
+    /// Rows per second written, as an exponentially weighted moving average
+    /// that gives each new sample a weight of 0.2. A sample is taken on every
+    /// write that comes after some time has passed, so the rate is not
+    /// lowered while writes stop. `None` until the first sample.
+    #[must_use]
+    pub const fn ingest_rate(&self) -> Option<f64> {
+        self.ingest.rate
+    }
+
+    /// Estimated time until the row limit is reached at the current
+    /// [`ingest_rate`], or `None` without a row limit or ingest.
+    ///
+    /// [`ingest_rate`]: Self::ingest_rate
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn estimated_time_to_row_limit(&self) -> Option<Duration> {
+        let rows = self.rows_until_flush()?;
+        let rate = self.ingest_rate().filter(|rate| *rate > 0.0)?;
+        Some(Duration::from_secs_f64(rows as f64 / rate))
+    }
+
+    /// Combined limit pressure, `0.5 * rows / max_rows + 0.3 * bytes /
+    /// max_bytes + 0.2 * elapsed / period`, where `elapsed` is the time since
+    /// the period timer started. A term is zero while its limit is unset, and
+    /// the bytes term also without a [`with_size_fn`], so all three limits are
+    /// needed for the score to reach `1.0` before any of them does.
+    ///
+    /// [`with_size_fn`]: Self::with_size_fn
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn pressure(&self) -> f64 {
+        let rows = if self.max_rows == u64::MAX {
+            0.0
+        } else {
+            self.pending.rows as f64 / self.max_rows as f64
+        };
+        let bytes = if self.size_fn.is_none() || self.max_bytes == u64::MAX {
+            0.0
+        } else {
+            self.pending.bytes as f64 / self.max_bytes as f64
+        };
+        let time = self.ticks.period_elapsed_fraction().unwrap_or(0.0);
+        0.5f64.mul_add(rows, 0.3f64.mul_add(bytes, 0.2 * time))
+    }
+
+    /// Whether any limit is reached, i.e. whether [`commit`](Self::commit)
+    /// would flush now. This calls the limit of
+    /// [`with_limit_fn`](Self::with_limit_fn), hence `&mut self`.
+    #[must_use]
+    pub fn is_due(&mut self) -> bool {
+        !self.is_throttled() && self.limits_reached()
+    }
+
+    /// Pushes the inserter's time forward by `elapsed`, for simulations
+    /// driven by an external event loop. Time-based limits see the advanced
+    /// time on top of the configured clock.
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.ticks.advance(elapsed);
+    }
+
+    /// Starts the period timer from now, even if it is already running or was
+    /// disarmed.
+    pub fn arm(&mut self) {
+        self.ticks.arm();
+    }
+
+    /// Stops the period timer until [`arm`](Self::arm) is called, keeping the
+    /// configured period. Other limits keep working.
+    pub const fn disarm(&mut self) {
+        self.ticks.disarm();
+    }
+
+    fn limits_reached(&mut self) -> bool {
+        self.full_flush_due() || self.partial_flush_due()
+    }
+
+    fn full_flush_due(&mut self) -> bool {
+        self.pending.rows >= self.max_rows
+            || self.pending.transactions >= self.max_transactions
+            || (self.size_fn.is_some() && self.pending.bytes >= self.max_bytes)
+            || self
+                .commit_every
+                .is_some_and(|every| self.writes > 0 && self.writes % every == 0)
+            || (self.ticks.reached() && self.pending.rows >= self.min_rows)
+            || self.idle_reached()
+            || self
+                .pressure_threshold
+                .is_some_and(|threshold| self.pressure() >= threshold)
+            || (self.buckets.is_none() && !self.priority.is_empty())
+            || (!self.pending.is_empty()
+                && self.memory_pressure.as_ref().is_some_and(|check| check()))
+            || self
+                .limit_fn
+                .as_mut()
+                .is_some_and(|limit| limit(&self.pending, &self.buffer))
+    }
+
+    fn idle_reached(&self) -> bool {
+        let (Some(idle_timeout), Some(last_write_at)) = (self.idle_timeout, self.last_write_at)
+        else {
+            return false;
+        };
+        !self.pending.is_empty()
+            && self.ticks.now().saturating_duration_since(last_write_at) >= idle_timeout
+    }
+
+    fn partial_flush_due(&self) -> bool {
+        self.buckets.as_ref().is_some_and(|buckets| {
+            !self.priority.is_empty() || buckets.has_closed(self.ticks.now())
+        })
+    }
+
+    fn is_throttled(&self) -> bool {
+        let (Some(interval), Some(last_flush_at)) = (self.min_flush_interval, self.last_flush_at)
+        else {
+            return false;
+        };
+        self.priority.is_empty()
+            && self.ticks.now().saturating_duration_since(last_flush_at) < interval
+    }
+
+    fn size_of(&self, item: &T) -> u64 {
+        self.size_fn
+            .as_ref()
+            .map_or(0, |size_fn| size_fn(item) as u64)
+    }
+
+    fn start_if_needed(&mut self) {
+        self.ticks.start();
+    }
+
+    fn is_buffer_full(&self) -> bool {
+        self.buffer.len() >= self.buffer_limit
+    }
+
+    fn admit(&mut self, item: &T) -> bool {
+        #[cfg(feature = "period_bias")]
+        if let Some(rate) = self.sample_rate {
+            if !self.ticks.sample(rate) {
+                self.dropped += 1;
+                return false;
+            }
+        }
+        if let Some(ref mut dedup) = self.dedup {
+            if !dedup.admit(item, self.ticks.now()) {
+                self.dropped += 1;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Counts a write; for regular items it must run before the item is
+    /// pushed so that a new transaction starts at the right buffer index.
+    fn record_write(&mut self, item: &T) {
+        self.pending.rows += 1;
+        self.pending.bytes += self.size_of(item);
+        self.writes += 1;
+        self.last_write_at = Some(self.ticks.now());
+        self.batch_started_at = self.batch_started_at.or(self.last_write_at);
+        self.ingest.record(self.ticks.now());
+
+        if self.track_transactions && !self.in_transaction {
+            self.pending.transactions += 1;
+            self.in_transaction = true;
+            self.open_from = self.buffer.len();
+        }
+    }
+
+    pub fn write_owned(&mut self, item: T) {
+        // Overflow is checked before deduplication so that the key of a
+        // dropped item is not remembered.
+        let full = self.is_buffer_full();
+        if full && self.overflow_policy == OverflowPolicy::DropNewest {
+            self.dropped += 1;
+            return;
+        }
+        if !self.admit(&item) {
+            return;
+        }
+
+        if full && self.overflow_policy == OverflowPolicy::DropOldest {
+            if !self.buffer.is_empty() {
+                let oldest = self.buffer.remove(0);
+                self.open_from = self.open_from.saturating_sub(1);
+                self.pending.rows -= 1;
+                self.pending.bytes -= self.size_of(&oldest);
+                if let Some(ref mut dedup) = self.dedup {
+                    dedup.forget(&oldest);
+                }
+            }
+            self.dropped += 1;
+        }
+
+        self.start_if_needed();
+
+        if let Some(ref mut buckets) = self.buckets {
+            buckets.record(&item);
+        }
+        self.record_write(&item);
+        self.buffer.push(item);
+    }
+
+    /// Writes `item` as part of transaction `txn_id`. Rows sharing an id count
+    /// as one transaction per flush, even when interleaved with other ids.
+    pub fn write_in_transaction(&mut self, txn_id: u64, item: T) {
+        let writes = self.writes;
+        let in_transaction = std::mem::replace(&mut self.in_transaction, true);
+        self.write_owned(item);
+        self.in_transaction = in_transaction;
+
+        if self.track_transactions && self.writes > writes && self.txn_ids.insert(txn_id) {
+            self.pending.transactions += 1;
+        }
+    }
+
+    /// Closes the open transaction, so the next write starts a new one.
+    pub const fn end_transaction(&mut self) {
+        self.in_transaction = false;
+    }
+
+    /// Starts a transaction that ends when the returned guard is dropped.
+    pub const fn transaction(&mut self) -> Transaction<'_, T, F, Fut, E> {
+        Transaction::new(self)
+    }
+
+    /// Turns the inserter into a [`Sink`](futures_sink::Sink) of rows, e.g.
+    /// for `stream.forward(inserter.into_sink())`.
+    #[cfg(feature = "sink")]
+    #[must_use]
+    pub const fn into_sink(self) -> InserterSink<T, F, Fut, E> {
+        InserterSink::new(self)
+    }
+
+    /// Writes every item from `items`, as if by calling [`write_owned`] for
+    /// each. Without an open transaction the items form one.
+    ///
+    /// [`write_owned`]: Self::write_owned
+    pub fn write_many<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        let items = items.into_iter();
+        self.buffer.reserve(items.size_hint().0);
+        for item in items {
+            self.write_owned(item);
+        }
+    }
+
+    /// Writes every `Ok` item from `items`, stopping at the first `Err`.
+    ///
+    /// Returns the number of items written. Items written before the error
+    /// stay buffered.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error yielded by `items`.
+    pub fn try_write_many<I, E2>(&mut self, items: I) -> Result<usize, E2>
+    where
+        I: IntoIterator<Item = Result<T, E2>>,
+    {
+        let mut written = 0;
+        for item in items {
+            self.write_owned(item?);
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Buffers a high-priority item that makes the next [`commit`] flush
+    /// regardless of limits.
+    ///
+    /// Priority items are sent at the front of the batch, in write order,
+    /// followed by the regular buffer. They are not subject to the buffer
+    /// limit.
+    ///
+    /// [`commit`]: Self::commit
+    pub fn write_priority(&mut self, item: T) {
+        if !self.admit(&item) {
+            return;
+        }
+
+        self.start_if_needed();
+
+        self.record_write(&item);
+        self.priority.push(item);
+    }
+
+    /// Removes and returns the buffered items matching `pred`, priority items
+    /// first, without flushing. Non-matching items stay buffered in order.
+    pub fn extract_matching<P>(&mut self, mut pred: P) -> Vec<T>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let (mut extracted, priority): (Vec<T>, Vec<T>) = std::mem::take(&mut self.priority)
+            .into_iter()
+            .partition(&mut pred);
+        let mut index = 0;
+        let mut extracted_closed = 0;
+        let (matching, buffer): (Vec<T>, Vec<T>) = std::mem::take(&mut self.buffer)
+            .into_iter()
+            .partition(|item| {
+                let matched = pred(item);
+                if matched && index < self.open_from {
+                    extracted_closed += 1;
+                }
+                index += 1;
+                matched
+            });
+        self.priority = priority;
+        self.buffer = buffer;
+        self.open_from -= extracted_closed;
+        extracted.extend(matching);
+
+        self.pending.rows -= extracted.len() as u64;
+        self.pending.bytes -= extracted.iter().map(|item| self.size_of(item)).sum::<u64>();
+        extracted
+    }
+
+    /// Like [`write_owned`], but with [`OverflowPolicy::Block`] a full buffer
+    /// is flushed before the item is accepted.
+    ///
+    /// [`write_owned`]: Self::write_owned
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn write_async(&mut self, item: T) -> Result<(), InserterError<E>> {
+        if self.overflow_policy == OverflowPolicy::Block && self.is_buffer_full() {
+            self.force_commit().await?;
+        }
+
+        self.write_owned(item);
+        Ok(())
+    }
+
+    fn start_flush(&mut self) -> bool {
+        let priority = std::mem::take(&mut self.priority);
+        let buffer = std::mem::take(&mut self.buffer);
+
+        let batches = if let Some(ref mut buckets) = self.buckets {
+            let mut batches = vec![priority];
+            batches.extend(buckets.split_all(buffer));
+            batches
+        } else {
+            let mut batch = priority;
+            batch.extend(buffer);
+            vec![batch]
+        };
+
+        self.start_batches(batches)
+    }
+
+    /// Takes the priority items and the regular items of every closed
+    /// transaction, leaving the open transaction buffered.
+    fn start_complete_transactions_flush(&mut self) -> bool {
+        if !self.in_transaction {
+            return self.start_flush();
+        }
+
+        let open = self.buffer.split_off(self.open_from.min(self.buffer.len()));
+        let complete = std::mem::replace(&mut self.buffer, open);
+        self.open_from = 0;
+
+        let mut batches = vec![std::mem::take(&mut self.priority)];
+        if let Some(ref mut buckets) = self.buckets {
+            batches.extend(buckets.split_all(complete));
+            for item in &self.buffer {
+                buckets.record(item);
+            }
+        } else {
+            batches.push(complete);
+        }
+
+        self.start_batches(batches)
+    }
+
+    /// Takes the priority items and up to `max_chunks` chunks of `max_rows`
+    /// regular items from the front of the buffer.
+    fn start_chunked_flush(&mut self, max_chunks: usize) -> bool {
+        let chunk_rows = usize::try_from(self.max_rows).unwrap_or(usize::MAX).max(1);
+        let taken = chunk_rows.saturating_mul(max_chunks).min(self.buffer.len());
+        let rest = self.buffer.split_off(taken);
+        let head = std::mem::replace(&mut self.buffer, rest);
+        self.open_from = self.open_from.saturating_sub(taken);
+
+        let mut batches = vec![std::mem::take(&mut self.priority)];
+        let mut head = head.into_iter();
+        while head.len() > 0 {
+            batches.push(head.by_ref().take(chunk_rows).collect());
+        }
+
+        self.start_batches(batches)
+    }
+
+    fn start_closed_buckets_flush(&mut self) -> bool {
+        let now = self.ticks.now();
+        let mut batches = vec![std::mem::take(&mut self.priority)];
+
+        if let Some(ref mut buckets) = self.buckets {
+            let (closed, open) = buckets.split_closed(std::mem::take(&mut self.buffer), now);
+            batches.extend(closed);
+            self.buffer = open;
+        }
+
+        self.start_batches(batches)
+    }
+
+    /// Starts inserting `batches`, already removed from the buffer. Returns
+    /// `false` if they hold no rows.
+    fn start_batches(&mut self, batches: Vec<Vec<T>>) -> bool {
+        let rows = batches.iter().map(Vec::len).sum::<usize>() as u64;
+        if rows == 0 {
+            return false;
+        }
+
+        let full = self.buffer.is_empty();
+        let mut flushed = if full {
+            *self.pending
+        } else {
+            Quantities {
+                rows,
+                transactions: self.pending.transactions.saturating_sub(1),
+                bytes: batches
+                    .iter()
+                    .flatten()
+                    .map(|item| self.size_of(item))
+                    .sum(),
+            }
+        };
+
+        if let Some(ref mut pre_commit) = self.pre_commit {
+            let approved = batches
+                .iter()
+                .filter(|batch| !batch.is_empty())
+                .all(|batch| pre_commit(batch, &flushed));
+            if !approved {
+                self.restore_vetoed(batches);
+                return false;
+            }
+        }
+
+        let in_transaction = self.in_transaction;
+        let batch_started_at = self.batch_started_at;
+        self.open_from = self.open_from.min(self.buffer.len());
+        self.txn_ids.clear();
+        if full {
+            self.in_transaction = false;
+            self.batch_started_at = None;
+            self.reserve_capacity();
+            *self.pending = Quantities::ZERO;
+        } else {
+            self.pending.rows -= flushed.rows;
+            self.pending.transactions -= flushed.transactions;
+            self.pending.bytes -= flushed.bytes;
+        }
+
+        if let Some(ref mut extra) = self.extra {
+            extra.start(&batches);
+        }
+        let snapshot = self.on_commit_rows.as_ref().map(|rows_callback| {
+            batches
+                .iter()
+                .flat_map(|batch| (rows_callback.snapshot)(batch))
+                .collect()
+        });
+
+        let mut batches = self.split_batches(batches);
+        let restore = self.restore_copy().map(|copy| Restore {
+            batches: batches.iter().map(|batch| copy(batch)).collect(),
+            flushed,
+            in_transaction,
+            batch_started_at,
+            partial: false,
+        });
+        if let Some(ref mut finalizer) = self.finalizer {
+            for batch in &mut batches {
+                finalizer(batch);
+            }
+            let finalized = batches.iter().map(Vec::len).sum::<usize>() as u64;
+            flushed.rows = flushed.rows - rows + finalized;
+        }
+        if batches.is_empty() {
+            return false;
+        }
+
+        if let Some(affected) = &self.affected {
+            affected.store(0, Ordering::Relaxed);
+        }
+        // Batches are started by `poll_slots`, once the permit is acquired.
+        self.in_flight = Some(InFlight {
+            slots: Vec::new(),
+            restore,
+            remaining: batches,
+            flushed,
+            committed: Quantities::ZERO,
+            written: rows,
+            snapshot,
+            tees: Vec::new(),
+            started_at: self.ticks.now(),
+            #[cfg(feature = "tokio")]
+            permit: self.semaphore.clone().map(hold_permit),
+        });
+        true
+    }
+
+    fn finish_flush(&mut self, in_flight: InFlight<T, Fut>) -> Quantities {
+        let mut flushed = in_flight.flushed;
+        self.submitted += flushed.rows;
+        self.checkpoint += in_flight.written;
+        if let Some(affected) = &self.affected {
+            flushed.rows = affected.swap(0, Ordering::Relaxed) + in_flight.committed.rows;
+        }
+
+        // Chunks before the last were committed as they completed.
+        let last = Quantities {
+            rows: flushed.rows.saturating_sub(in_flight.committed.rows),
+            transactions: flushed.transactions,
+            bytes: flushed.bytes.saturating_sub(in_flight.committed.bytes),
+        };
+        self.committed += last;
+        let now = self.ticks.now();
+        self.flushes += 1;
+        self.last_flush_at = Some(now);
+        self.max_flush_latency = self
+            .max_flush_latency
+            .max(now.saturating_duration_since(in_flight.started_at));
+
+        if let Some(ref mut extra) = self.extra {
+            extra.finish();
+        }
+        if let Some(ref mut callback) = self.on_commit {
+            callback(if self.commit_per_chunk {
+                &last
+            } else {
+                &flushed
+            });
+        }
+        if let Some(ref mut callback) = self.on_checkpoint {
+            callback(self.checkpoint);
+        }
+        if let (Some(rows_callback), Some(rows)) = (&mut self.on_commit_rows, in_flight.snapshot) {
+            (rows_callback.callback)(&rows);
+        }
+
+        flushed
+    }
+
+    /// Drops empty batches, then splits the rest by group key and chunk size
+    /// into the batches handed to the insert function.
+    fn split_batches(&mut self, batches: Vec<Vec<T>>) -> VecDeque<Vec<T>> {
+        let mut batches: VecDeque<Vec<T>> = batches
+            .into_iter()
+            .filter(|batch| !batch.is_empty())
+            .collect();
+        if let Some(ref mut grouper) = self.grouper {
+            batches = batches.into_iter().flat_map(grouper).collect();
+        }
+        if let Some(chunk_size) = self.chunk_size {
+            batches = batches
+                .into_iter()
+                .flat_map(|batch| {
+                    let mut rows = batch.into_iter();
+                    let mut chunks = Vec::new();
+                    while rows.len() > 0 {
+                        chunks.push(rows.by_ref().take(chunk_size).collect());
+                    }
+                    chunks
+                })
+                .collect();
+        }
+        batches
+    }
+
+    /// Rows and bytes of `chunk`, counted per chunk with
+    /// [`with_chunk_size`](Self::with_chunk_size).
+    fn chunk_quantities(&self, chunk: &[T]) -> Quantities {
+        if self.chunk_size.is_none() {
+            return Quantities::ZERO;
+        }
+        Quantities {
+            rows: chunk.len() as u64,
+            transactions: 0,
+            bytes: chunk.iter().map(|item| self.size_of(item)).sum(),
+        }
+    }
+
+    /// Fails `slot` with a timeout once its
+    /// [`with_insert_timeout`](Self::with_insert_timeout) elapsed.
+    #[cfg(feature = "tokio")]
+    fn poll_deadline(
+        &self,
+        slot: &mut Slot<T, Fut>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), InserterError<E>>> {
+        let (Some(limit), Some(deadline)) = (&self.insert_timeout, slot.deadline.as_mut()) else {
+            return Poll::Pending;
+        };
+        ready!(deadline.as_mut().poll(cx));
+        Poll::Ready(Err(InserterError::timeout(limit.timeout)))
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    #[allow(clippy::unused_self)]
+    const fn poll_deadline(
+        &self,
+        _slot: &Slot<T, Fut>,
+        _cx: &Context<'_>,
+    ) -> Poll<Result<(), InserterError<E>>> {
+        Poll::Pending
+    }
+
+    /// Copies batches for [`Restore`] whenever a failed flush may put them
+    /// back.
+    fn restore_copy(&self) -> Option<CopyBatch<T>> {
+        #[cfg(feature = "tokio")]
+        if self.keep_on_failure.is_none() {
+            return self.insert_timeout.as_ref().map(|limit| limit.copy);
+        }
+        self.keep_on_failure
+    }
+
+    /// Timer for a batch whose insert starts now.
+    #[cfg(feature = "tokio")]
+    fn deadline(&self) -> Option<Pin<Box<Sleep>>> {
+        self.insert_timeout
+            .as_ref()
+            .map(|limit| Box::pin(sleep(limit.timeout)))
+    }
+
+    /// Starts inserting `batch`, sending its copy with
+    /// [`with_tee`](Self::with_tee) to `tees` first.
+    fn start_slot(&mut self, batch: Vec<T>, tees: &mut Vec<TeeFuture>) -> Slot<T, Fut> {
+        if let Some(ref mut tee) = self.tee {
+            tees.push(tee(&batch));
+        }
+        let sequence = self.sequences.as_mut().map_or(0, Sequences::take);
+        Slot {
+            batch: self.retry.as_ref().map(|retry| (retry.copy)(&batch)),
+            attempt: 1,
+            sequence,
+            sent: self.chunk_quantities(&batch),
+            done: false,
+            #[cfg(feature = "tokio")]
+            deadline: self.deadline(),
+            future: Box::pin(self.insert(batch, sequence)),
+        }
+    }
+
+    /// Calls the insert function for the batch numbered `sequence`.
+    fn insert(&mut self, batch: Vec<T>, sequence: u64) -> Fut {
+        if let Some(sequences) = &self.sequences {
+            sequences.current.store(sequence, Ordering::Relaxed);
+        }
+        (self.insert_fn)(batch)
+    }
+
+    /// Counts a chunk that succeeded before the end of its flush as
+    /// committed.
+    fn commit_chunk(&mut self, in_flight: &mut InFlight<T, Fut>, sent: Quantities) {
+        if self.chunk_size.is_none() {
+            return;
+        }
+        let mut done = sent;
+        if let Some(affected) = &self.affected {
+            done.rows = affected.swap(0, Ordering::Relaxed);
+        }
+        self.committed += done;
+        in_flight.committed += done;
+        if self.commit_per_chunk {
+            if let Some(ref mut callback) = self.on_commit {
+                callback(&done);
+            }
+        }
+    }
+
+    /// Polls the batches of `in_flight`, keeping up to the flush concurrency
+    /// of them running, until all succeeded or one failed for good.
+    fn poll_slots(
+        &mut self,
+        in_flight: &mut InFlight<T, Fut>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), InserterError<E>>> {
+        loop {
+            while in_flight.running() < self.flush_concurrency {
+                let Some(batch) = in_flight.remaining.pop_front() else {
+                    break;
+                };
+                let slot = self.start_slot(batch, &mut in_flight.tees);
+                in_flight.slots.push(slot);
+            }
+            self.max_in_flight = self.max_in_flight.max(in_flight.running());
+            if in_flight.slots.is_empty() {
+                return self.poll_tees(in_flight, cx).map(Ok);
+            }
+
+            let mut completed = false;
+            let mut index = 0;
+            while index < in_flight.slots.len() {
+                let slot = &mut in_flight.slots[index];
+                if slot.done {
+                    index += 1;
+                    continue;
+                }
+                let poll = match slot.future.as_mut().poll(cx) {
+                    Poll::Ready(result) => Poll::Ready(result.map_err(InserterError::new)),
+                    Poll::Pending => self.poll_deadline(slot, cx),
+                };
+                match poll {
+                    Poll::Pending => index += 1,
+                    // The first slot is never held, so an earlier batch is
+                    // still running.
+                    Poll::Ready(Ok(())) if self.ordered_flush && index > 0 => {
+                        slot.done = true;
+                        slot.batch = None;
+                        if let Some(ref mut restore) = in_flight.restore {
+                            restore.batches[index].clear();
+                            restore.partial = true;
+                        }
+                        index += 1;
+                        completed = true;
+                    }
+                    Poll::Ready(Ok(())) => {
+                        self.complete_slot(in_flight, index);
+                        while in_flight.slots.first().is_some_and(|slot| slot.done) {
+                            self.complete_slot(in_flight, 0);
+                        }
+                        completed = true;
+                    }
+                    Poll::Ready(Err(err)) => {
+                        let attempt = slot.attempt;
+                        let retry = self.retry.as_ref().filter(|retry| attempt < retry.attempts);
+                        let (Some(retry), Some(batch)) = (retry, &slot.batch) else {
+                            self.complete_held(in_flight);
+                            return Poll::Ready(Err(err.with_attempts(attempt)));
+                        };
+                        let batch = (retry.copy)(batch);
+                        slot.attempt += 1;
+                        self.retries += 1;
+                        slot.future = Box::pin(self.insert(batch, slot.sequence));
+                        #[cfg(feature = "tokio")]
+                        {
+                            slot.deadline = self.deadline();
+                        }
+                    }
+                }
+            }
+            if !completed {
+                let _ = self.poll_tees(in_flight, cx);
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Removes the batch at `index`, which succeeded, and commits it unless
+    /// it is the last of its flush.
+    fn complete_slot(&mut self, in_flight: &mut InFlight<T, Fut>, index: usize) {
+        let slot = in_flight.slots.remove(index);
+        if let Some(ref mut restore) = in_flight.restore {
+            restore.batches.remove(index);
+            restore.partial = true;
+        }
+        if !in_flight.slots.is_empty() || !in_flight.remaining.is_empty() {
+            self.commit_chunk(in_flight, slot.sent);
+        }
+    }
+
+    /// Commits the batches held by [`with_ordered_flush`] when their flush
+    /// fails, in the order they were started, since they were inserted.
+    ///
+    /// [`with_ordered_flush`]: Self::with_ordered_flush
+    fn complete_held(&mut self, in_flight: &mut InFlight<T, Fut>) {
+        let mut index = 0;
+        while index < in_flight.slots.len() {
+            if in_flight.slots[index].done {
+                self.complete_slot(in_flight, index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Polls the copies sent with [`with_tee`](Self::with_tee), reporting
+    /// failed ones to the observer, until all are done.
+    fn poll_tees(&mut self, in_flight: &mut InFlight<T, Fut>, cx: &mut Context<'_>) -> Poll<()> {
+        in_flight.tees.retain_mut(|tee| {
+            let Poll::Ready(result) = tee.as_mut().poll(cx) else {
+                return true;
+            };
+            if let (Err(err), Some(observer)) = (result, &mut self.observer) {
+                observer.on_tee_error(&*err);
+            }
+            false
+        });
+        if in_flight.tees.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Puts back what a failed flush did not insert: every row of it with
+    /// [`with_restore_on_failure`] or if it timed out, otherwise the chunks
+    /// not sent yet with [`with_chunk_size`].
+    ///
+    /// [`with_restore_on_failure`]: Self::with_restore_on_failure
+    /// [`with_chunk_size`]: Self::with_chunk_size
+    fn restore_unsent(&mut self, failed: InFlight<T, Fut>, timed_out: bool) {
+        let restore = failed
+            .restore
+            .filter(|_| timed_out || self.keep_on_failure.is_some());
+        if let Some(restore) = restore {
+            if let Some(ref mut sequences) = self.sequences {
+                // Started batches keep their numbers; the others never got one.
+                let started = failed.slots.iter().filter(|slot| !slot.done);
+                for sequence in started.map(|slot| slot.sequence).rev() {
+                    sequences.restored.push_front(sequence);
+                }
+            }
+            self.restore_failed(restore);
+        } else if self.chunk_size.is_some() && !failed.remaining.is_empty() {
+            self.restore_failed(Restore {
+                batches: failed.remaining,
+                flushed: failed.flushed,
+                in_transaction: false,
+                batch_started_at: None,
+                partial: true,
+            });
+        }
+    }
+
+    /// Puts the rows of a flush cancelled by the pre-commit callback back at
+    /// the front of the buffer; counters were not touched yet.
+    fn restore_vetoed(&mut self, batches: Vec<Vec<T>>) {
+        let mut rows: Vec<T> = batches.into_iter().flatten().collect();
+        if let Some(ref mut buckets) = self.buckets {
+            for item in rows.iter().chain(&self.buffer) {
+                buckets.record(item);
+            }
+        }
+        // A full flush took the whole buffer, so the open transaction still
+        // starts where it did; otherwise it is in what remained buffered.
+        if !self.buffer.is_empty() {
+            self.open_from = self.open_from.min(self.buffer.len()) + rows.len();
+        }
+        rows.append(&mut self.buffer);
+        self.buffer = rows;
+    }
+
+    /// Puts the rows of a failed flush back at the front of the buffer, as if
+    /// the flush had not started.
+    fn restore_failed(&mut self, restore: Restore<T>) {
+        let mut rows: Vec<T> = restore.batches.into_iter().flatten().collect();
+        let restored = if restore.partial {
+            // Earlier batches of the flush were inserted; count the rest as
+            // one transaction.
+            Quantities {
+                rows: rows.len() as u64,
+                transactions: u64::from(restore.flushed.transactions > 0),
+                bytes: rows.iter().map(|item| self.size_of(item)).sum(),
+            }
+        } else {
+            restore.flushed
+        };
+
+        *self.pending += restored;
+        self.in_transaction |= restore.in_transaction;
+        self.batch_started_at = restore
+            .batch_started_at
+            .into_iter()
+            .chain(self.batch_started_at)
+            .min();
+        self.open_from += rows.len();
+        rows.append(&mut self.buffer);
+        self.buffer = rows;
+    }
+
+    /// Whether a flush was started and has not completed yet, including its
+    /// async commit callback.
+    const fn is_flushing(&self) -> bool {
+        self.in_flight.is_some() || self.commit_notice.is_some()
+    }
+
+    /// Flushes everything buffered. A flush already in flight is completed
+    /// first, then rows written meanwhile are flushed after it.
+    pub(crate) fn poll_flush(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Quantities, InserterError<E>>> {
+        loop {
+            let resumed = self.is_flushing();
+            let flushed = ready!(self.poll_flush_once(cx))?;
+            if !resumed || self.is_buffer_empty() {
+                return Poll::Ready(Ok(flushed));
+            }
+            self.carried = flushed;
+        }
+    }
+
+    /// Completes the flush in flight, or starts and completes a flush of
+    /// everything buffered if there is none.
+    fn poll_flush_once(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Quantities, InserterError<E>>> {
+        let result = ready!(self.poll_in_flight(cx));
+        let carried = std::mem::replace(&mut self.carried, Quantities::ZERO);
+        Poll::Ready(result.map(|flushed| carried + flushed))
+    }
+
+    fn poll_in_flight(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Quantities, InserterError<E>>> {
+        if !self.is_flushing() && !self.start_flush() {
+            return Poll::Ready(Ok(Quantities::ZERO));
+        }
+
+        // The flush is taken out while it is polled, so that completed
+        // chunks can update the inserter, and put back if it is pending.
+        if let Some(mut in_flight) = self.in_flight.take() {
+            #[cfg(feature = "tokio")]
+            if let Some(permit) = in_flight.permit.as_mut() {
+                if permit.as_mut().poll(cx).is_pending() {
+                    self.in_flight = Some(in_flight);
+                    return Poll::Pending;
+                }
+            }
+
+            match self.poll_slots(&mut in_flight, cx) {
+                Poll::Pending => {
+                    self.in_flight = Some(in_flight);
+                    return Poll::Pending;
+                }
+                Poll::Ready(Err(err)) => {
+                    if let (Some(callback), InserterError::Insert { source, .. }) =
+                        (&mut self.on_error, &err)
+                    {
+                        callback(source, &in_flight.flushed);
+                    }
+                    self.restore_unsent(in_flight, err.is_timeout());
+                    return Poll::Ready(Err(err));
+                }
+                Poll::Ready(Ok(())) => {
+                    let flushed = self.finish_flush(in_flight);
+                    let Some(ref mut callback) = self.on_commit_async else {
+                        return Poll::Ready(Ok(flushed));
+                    };
+                    self.commit_notice = Some(CommitNotice {
+                        future: callback(&flushed),
+                        flushed,
+                    });
+                }
+            }
+        }
+
+        if let Some(notice) = self.commit_notice.as_mut() {
+            ready!(notice.future.as_mut().poll(cx));
+            let flushed = notice.flushed;
+            self.commit_notice = None;
+            return Poll::Ready(Ok(flushed));
+        }
+
+        Poll::Ready(Ok(Quantities::ZERO))
+    }
+
+    /// Flushes everything buffered, like [`force_commit`], but leaves the
+    /// period timer alone, for callers that schedule flushes themselves.
+    ///
+    /// [`force_commit`]: Self::force_commit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn flush(&mut self) -> Result<Quantities, InserterError<E>> {
+        poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    /// Poll-based form of [`commit`] for manual polling or custom executors.
+    ///
+    /// The in-flight insert is kept inside the inserter between calls, so a
+    /// pending flush is resumed by the next `poll_commit`, [`commit`] or
+    /// [`force_commit`] rather than restarted.
+    ///
+    /// [`commit`]: Self::commit
+    /// [`force_commit`]: Self::force_commit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub fn poll_commit(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Quantities, InserterError<E>>> {
+        let flushing = self.is_flushing()
+            || (!self.is_throttled()
+                && (self.full_flush_due()
+                    || (self.partial_flush_due() && self.start_closed_buckets_flush())));
+        if !flushing {
+            self.in_transaction = false;
+            return Poll::Ready(Ok(Quantities::ZERO));
+        }
+
+        let result = ready!(self.poll_flush_once(cx))?;
+        self.reschedule();
+        Poll::Ready(Ok(result))
+    }
+
+    fn reschedule(&mut self) {
+        if let Some(missed_by) = self.ticks.reschedule() {
+            if let Some(ref mut callback) = self.on_schedule_miss {
+                callback(missed_by);
+            }
+        }
+    }
+
+    fn poll_force_commit(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Quantities, InserterError<E>>> {
+        let result = ready!(self.poll_flush(cx))?;
+        self.reschedule();
+        Poll::Ready(Ok(result))
+    }
+
+    /// Checks limits and flushes if reached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn commit(&mut self) -> Result<Quantities, InserterError<E>> {
+        poll_fn(|cx| self.poll_commit(cx)).await
+    }
+
+    /// Like [`commit`], but returns whether a flush actually completed,
+    /// which empty [`Quantities`] cannot tell apart from a skipped commit.
+    ///
+    /// [`commit`]: Self::commit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn commit_flushed(&mut self) -> Result<bool, InserterError<E>> {
+        let flushes = self.flushes;
+        self.commit().await?;
+        Ok(self.flushes != flushes)
+    }
+
+    /// Like [`commit`], but inserts at most `max_chunks` chunks of up to
+    /// `max_rows` rows per call and leaves the rest buffered, so a large
+    /// backlog drains over several calls. Priority items are always included.
+    ///
+    /// [`commit`]: Self::commit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn commit_budget(
+        &mut self,
+        max_chunks: usize,
+    ) -> Result<Quantities, InserterError<E>> {
+        if !(self.is_flushing() || (self.is_due() && self.start_chunked_flush(max_chunks))) {
+            self.in_transaction = false;
+            return Ok(Quantities::ZERO);
+        }
+
+        let flushed = poll_fn(|cx| self.poll_flush_once(cx)).await?;
+        self.reschedule();
+        Ok(flushed)
+    }
+
+    /// Returns a flush of the whole buffer if there is buffered data and
+    /// nothing has been written for at least `idle_threshold`, so a shared
+    /// ticker can cheaply check many inserters and flush only idle ones.
+    pub fn flush_if_idle(
+        &mut self,
+        idle_threshold: Duration,
+    ) -> Option<impl Future<Output = Result<Quantities, InserterError<E>>> + '_> {
+        let now = self.ticks.now();
+        let idle = self
+            .last_write_at
+            .is_some_and(|at| now.saturating_duration_since(at) >= idle_threshold);
+        (idle && !self.pending.is_empty()).then(|| self.force_commit())
+    }
+
+    /// Flushes unconditionally, regardless of limits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn force_commit(&mut self) -> Result<Quantities, InserterError<E>> {
+        poll_fn(|cx| self.poll_force_commit(cx)).await
+    }
+
+    /// Waits for the notify set with [`with_flush_notify`], then flushes
+    /// everything buffered. Notifications sent since the previous cut, even
+    /// while this was not being awaited, count. Without a notify this is
+    /// [`force_commit`].
+    ///
+    /// [`with_flush_notify`]: Self::with_flush_notify
+    /// [`force_commit`]: Self::force_commit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    #[cfg(feature = "tokio")]
+    pub async fn commit_on_notify(&mut self) -> Result<Quantities, InserterError<E>> {
+        if let Some(ref mut cut) = self.flush_notify {
+            cut.notified.as_mut().await;
+            *cut = FlushNotify::new(Arc::clone(&cut.notify));
+        }
+        self.force_commit().await
+    }
+
+    /// Flushes the rows of every closed transaction, keeping the rows of the
+    /// open one buffered. Priority items are always flushed. Without an open
+    /// transaction this flushes everything, like [`force_commit`].
+    ///
+    /// [`force_commit`]: Self::force_commit
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn flush_complete_transactions(&mut self) -> Result<Quantities, InserterError<E>> {
+        let mut flushed = if self.is_flushing() {
+            poll_fn(|cx| self.poll_flush_once(cx)).await?
+        } else {
+            Quantities::ZERO
+        };
+
+        if self.start_complete_transactions_flush() {
+            flushed += poll_fn(|cx| self.poll_flush_once(cx)).await?;
+        }
+        Ok(flushed)
+    }
+
+    /// Consumes the inserter and flushes remaining buffered items.
+    ///
+    /// With [`with_final_empty_flush`] the insert function is called with an
+    /// empty batch if there was nothing left to flush.
+    ///
+    /// [`with_final_empty_flush`]: Self::with_final_empty_flush
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn end(self) -> Result<Quantities, InserterError<E>> {
+        self.end_or_recover().await.map_err(|(mut inserter, err)| {
+            // The error already reports the rows left behind.
+            inserter.pending.check = false;
+            err
+        })
+    }
+
+    /// Like [`end`], but hands the inserter back along with the error if a
+    /// flush fails, so it can be retried or reconfigured.
+    ///
+    /// The rows of the failed flush are lost unless the inserter was built
+    /// with [`with_restore_on_failure`]. By default the inserter handed back
+    /// only gets back chunks that were not sent yet with [`with_chunk_size`].
+    ///
+    /// [`end`]: Self::end
+    /// [`with_restore_on_failure`]: Self::with_restore_on_failure
+    /// [`with_chunk_size`]: Self::with_chunk_size
+    ///
+    /// # Errors
+    ///
+    /// Returns the inserter and the error if the insert function fails.
+    pub async fn end_or_recover(mut self) -> Result<Quantities, (Self, InserterError<E>)> {
+        match self.shut_down().await {
+            Ok(()) => Ok(self.committed),
+            Err(err) => Err((self, err)),
+        }
+    }
+
+    /// Flushes everything left and runs the empty insert of
+    /// [`with_final_empty_flush`](Self::with_final_empty_flush), shared by
+    /// every way of ending the inserter.
+    async fn shut_down(&mut self) -> Result<(), InserterError<E>> {
+        let mut state = ShutDown::Flushing;
+        poll_fn(|cx| self.poll_shut_down(&mut state, cx)).await
+    }
+
+    /// Poll-based form of [`shut_down`](Self::shut_down), resuming from
+    /// `state`. With [`new_detached`](Self::new_detached) it also waits for
+    /// the flusher to drain its queue.
+    pub(crate) fn poll_shut_down(
+        &mut self,
+        state: &mut ShutDown<Fut>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), InserterError<E>>> {
+        loop {
+            match state {
+                ShutDown::Flushing => {
+                    let flushed = ready!(self.poll_flush(cx))?;
+                    *state = self.final_empty_insert(flushed).map_or_else(
+                        || self.drain_state(),
+                        |future| ShutDown::EmptyInsert(Box::pin(future)),
+                    );
+                }
+                ShutDown::EmptyInsert(future) => {
+                    let result = ready!(future.as_mut().poll(cx));
+                    *state = ShutDown::Done;
+                    if let Err(err) = result {
+                        return Poll::Ready(Err(self.empty_insert_failed(err)));
+                    }
+                    *state = self.drain_state();
+                }
+                #[cfg(feature = "tokio")]
+                ShutDown::Draining => {
+                    let Some(queue) = self.detached.as_ref() else {
+                        *state = ShutDown::Done;
+                        continue;
+                    };
+                    *state = if ready!(flusher::poll_drained(queue, cx)) {
+                        ShutDown::Done
+                    } else {
+                        ShutDown::LostRows(Box::pin((self.insert_fn)(Vec::new())))
+                    };
+                }
+                #[cfg(feature = "tokio")]
+                ShutDown::LostRows(future) => {
+                    let result = ready!(future.as_mut().poll(cx));
+                    *state = ShutDown::Done;
+                    return Poll::Ready(result.map_err(InserterError::new));
+                }
+                ShutDown::Done => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+
+    /// State of [`poll_shut_down`](Self::poll_shut_down) once the final
+    /// flush is done.
+    #[cfg(feature = "tokio")]
+    const fn drain_state(&self) -> ShutDown<Fut> {
+        if self.detached.is_some() {
+            ShutDown::Draining
+        } else {
+            ShutDown::Done
+        }
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    #[allow(clippy::unused_self)]
+    const fn drain_state(&self) -> ShutDown<Fut> {
+        ShutDown::Done
+    }
+
+    /// Starts the empty insert of [`with_final_empty_flush`] if the final
+    /// flush, which flushed `flushed`, left it to do.
+    ///
+    /// [`with_final_empty_flush`]: Self::with_final_empty_flush
+    fn final_empty_insert(&mut self, flushed: Quantities) -> Option<Fut> {
+        if !self.final_empty_flush || flushed.rows > 0 {
+            return None;
+        }
+        let sequence = self.sequences.as_mut().map_or(0, Sequences::take);
+        Some(self.insert(Vec::new(), sequence))
+    }
+
+    fn empty_insert_failed(&mut self, err: E) -> InserterError<E> {
+        if let Some(ref mut callback) = self.on_error {
+            callback(&err, &Quantities::ZERO);
+        }
+        InserterError::new(err)
+    }
+
+    /// Like [`end`], but returns a summary of the inserter's whole lifetime.
+    ///
+    /// [`end`]: Self::end
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn finish(mut self) -> Result<ShutdownReport, InserterError<E>> {
+        if let Err(err) = self.shut_down().await {
+            self.pending.check = false;
+            return Err(err);
+        }
+        Ok(ShutdownReport {
+            committed: self.committed,
+            flushes: self.flushes,
+            dropped: self.dropped,
+            retries: self.retries,
+            max_flush_latency: self.max_flush_latency,
+            uptime: self.ticks.now().saturating_duration_since(self.created_at),
+        })
+    }
+
+    /// Writes items from `writes`, committing after each one, until either the
+    /// stream ends or `shutdown` resolves. Then drains the buffer via [`end`].
+    ///
+    /// Once `shutdown` resolves no further items are taken from the stream.
+    /// The period limit is only checked when an item arrives, so an idle stream
+    /// does not trigger time-based flushes on its own.
+    ///
+    /// [`end`]: Self::end
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    #[cfg(feature = "stream")]
+    pub async fn run_until_shutdown<S, Sd>(
+        mut self,
+        writes: S,
+        shutdown: Sd,
+    ) -> Result<Quantities, InserterError<E>>
+    where
+        S: Stream<Item = T>,
+        Sd: Future,
+    {
+        let mut writes = pin!(writes);
+        let mut shutdown = pin!(shutdown);
+
+        loop {
+            let next = poll_fn(|cx| {
+                if shutdown.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                writes.as_mut().poll_next(cx)
+            })
+            .await;
+
+            let Some(item) = next else {
+                break;
+            };
+
+            self.write_owned(item);
+            self.commit().await?;
+        }
+
+        self.end().await
+    }
+
+    /// Writes every item of `stream`, committing after each one, and returns
+    /// what was committed meanwhile. Rows still buffered when the stream ends
+    /// are left for the next commit.
+    ///
+    /// While the stream is idle the period is still honoured: `sleep` is
+    /// called with [`time_left`](Self::time_left) to get a timer, e.g.
+    /// `tokio::time::sleep`, and the inserter commits when it fires. A tick
+    /// that cannot flush yet, e.g. below the row floor, waits for the next
+    /// item.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    #[cfg(feature = "stream")]
+    pub async fn consume_stream<S, Sl, SlFut>(
+        &mut self,
+        stream: S,
+        mut sleep: Sl,
+    ) -> Result<Quantities, InserterError<E>>
+    where
+        S: Stream<Item = T>,
+        Sl: FnMut(Duration) -> SlFut,
+        SlFut: Future,
+    {
+        let mut stream = pin!(stream);
+        let mut committed = Quantities::ZERO;
+
+        loop {
+            let timer = self
+                .time_left()
+                .filter(|left| !left.is_zero())
+                .map(&mut sleep);
+            let mut timer = pin!(timer);
+            let next = poll_fn(|cx| {
+                if let Poll::Ready(item) = stream.as_mut().poll_next(cx) {
+                    return Poll::Ready(Some(item));
+                }
+                timer
+                    .as_mut()
+                    .as_pin_mut()
+                    .map_or(Poll::Pending, |timer| timer.poll(cx).map(|_| None))
+            })
+            .await;
+
+            match next {
+                Some(Some(item)) => self.write_owned(item),
+                Some(None) => return Ok(committed),
+                None => {}
+            }
+            committed += self.commit().await?;
+        }
+    }
+}
+
+impl<T> Inserter<T> {
+    /// Creates an inserter whose insert function discards every batch, for
+    /// benchmarks and tests.
+    #[must_use]
+    pub fn null() -> Self {
+        Self::new(|_batch| std::future::ready(Ok(())))
+    }
+
+    /// Creates an inserter whose insert function reports the number of rows
+    /// it actually wrote, e.g. for upserts that may skip rows. Committed and
+    /// flushed row counts then use the reported numbers, while
+    /// [`submitted_rows`](Inserter::submitted_rows) counts what was sent.
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn new_counting<F, Fut, E>(
+        mut insert_fn: F,
+    ) -> Inserter<T, impl FnMut(Vec<T>) -> Counting<Fut>, Counting<Fut>, E>
+    where
+        F: FnMut(Vec<T>) -> Fut,
+        Fut: Future<Output = Result<u64, E>>,
+        E: Error,
+    {
+        let affected = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&affected);
+        let mut inserter =
+            Inserter::new(move |batch| Counting::new(insert_fn(batch), Arc::clone(&counter)));
+        inserter.affected = Some(affected);
+        inserter
+    }
+
+    /// Creates an inserter whose insert function also gets an idempotency key
+    /// for every batch, computed by `key_fn` from the batch and its sequence
+    /// number, counting from 0. Retries of a batch reuse its key and sequence
+    /// number, so a sink can deduplicate a batch retried after an ambiguous
+    /// failure such as a timeout.
+    ///
+    /// Batches put back by a failed flush, with
+    /// [`with_restore_on_failure`](Self::with_restore_on_failure) or
+    /// after a timeout, keep their sequence numbers for the flush that
+    /// inserts their rows again. Rows written in between join those batches,
+    /// so only a key computed from the rows as well tells them apart.
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn new_idempotent<F, KF, K, Fut, E>(
+        mut insert_fn: F,
+        key_fn: KF,
+    ) -> Inserter<T, impl FnMut(Vec<T>) -> Fut, Fut, E>
+    where
+        F: FnMut(Vec<T>, K) -> Fut,
+        KF: Fn(&[T], u64) -> K,
+        Fut: Future<Output = Result<(), E>>,
+        E: Error,
+    {
+        let current = Arc::new(AtomicU64::new(0));
+        let sequence = Arc::clone(&current);
+        let mut inserter = Inserter::new(move |batch: Vec<T>| {
+            let key = key_fn(&batch, sequence.load(Ordering::Relaxed));
+            insert_fn(batch, key)
+        });
+        inserter.sequences = Some(Sequences {
+            current,
+            next: 0,
+            restored: VecDeque::new(),
+        });
+        inserter
+    }
+
+    /// Creates an inserter whose flushes hand batches to the returned
+    /// [`Flusher`] over a channel holding up to `capacity` batches, so they
+    /// complete as soon as the batch is queued and wait only while the
+    /// channel is full.
+    ///
+    /// Spawn the flusher on your runtime; it calls `insert_fn` for each
+    /// queued batch in order. Committed counts and commit callbacks count
+    /// queued batches, but [`end`](Inserter::end) and
+    /// [`finish`](Inserter::finish) only return once the flusher has inserted
+    /// everything. If it stopped first, e.g. because an insert failed, they
+    /// and every later flush fail with a [`FlusherClosed`] telling how many
+    /// queued rows were lost.
+    ///
+    /// This is a constructor rather than a builder on an existing inserter
+    /// because the inserter's own insert function is the sending side of the
+    /// channel, failing with [`FlusherClosed`]; `insert_fn` and its error type
+    /// belong to the flusher.
+    #[cfg(feature = "tokio")]
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn new_detached<F, Fut, E>(
+        capacity: usize,
+        insert_fn: F,
+    ) -> (
+        Inserter<T, impl FnMut(Vec<T>) -> SendBatch, SendBatch, FlusherClosed>,
+        Flusher<T, F, Fut>,
+    )
+    where
+        T: Send + 'static,
+        F: FnMut(Vec<T>) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+    {
+        let (send, flusher, queue) = flusher::channel(capacity, insert_fn);
+        let mut inserter = Inserter::new(send);
+        inserter.detached = Some(queue);
+        (inserter, flusher)
+    }
+
+    /// Creates an inserter whose insert function also gets a clone of the
+    /// batch metadata with every batch, e.g. a schema version. The metadata
+    /// starts out as `meta` and is replaced with
+    /// [`MetaInserter::set_batch_meta`] at runtime.
+    ///
+    /// ```
+    /// use std::convert::Infallible;
+    /// use universal_inserter::Inserter;
+    ///
+    /// let mut inserter = Inserter::new_with_meta(
+    ///     |_rows: Vec<u64>, _schema: &str| async { Ok::<_, Infallible>(()) },
+    ///     "schema-v1",
+    /// );
+    /// inserter.set_batch_meta("schema-v2");
+    /// ```
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn new_with_meta<F, M, Fut, E>(
+        mut insert_fn: F,
+        meta: M,
+    ) -> MetaInserter<T, impl FnMut(Vec<T>) -> Fut, Fut, E, M>
+    where
+        F: FnMut(Vec<T>, M) -> Fut,
+        M: Clone,
+        Fut: Future<Output = Result<(), E>>,
+        E: Error,
+    {
+        let meta = Arc::new(Mutex::new(meta));
+        let current = Arc::clone(&meta);
+        let inserter = Inserter::new(move |batch| {
+            let meta = current
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            insert_fn(batch, meta)
+        });
+        MetaInserter::new(inserter, meta)
+    }
+
+    /// Creates an inserter whose flushed batches are yielded by the returned
+    /// stream instead of being inserted anywhere, turning it into a batching
+    /// adapter. Batches flushed after the stream is dropped are discarded.
+    #[cfg(feature = "stream")]
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn batching_stream() -> (
+        Inserter<
+            T,
+            impl FnMut(Vec<T>) -> Ready<Result<(), Infallible>>,
+            Ready<Result<(), Infallible>>,
+            Infallible,
+        >,
+        BatchStream<T>,
+    ) {
+        let (sender, stream) = batch_stream::channel();
+        let inserter = Inserter::new(move |batch| {
+            sender.send(batch);
+            std::future::ready(Ok(()))
+        });
+        (inserter, stream)
+    }
+}
+
+impl<T, F, Fut, E> Inserter<T, F, Fut, E>
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    pub fn write(&mut self, item: &T) {
+        self.write_owned(item.clone());
+    }
+
+    /// Like [`write_many`](Self::write_many), but clones items from an
+    /// iterator of references such as `&[T]`.
+    pub fn write_many_ref<'a, I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a,
+    {
+        self.write_many(items.into_iter().cloned());
+    }
+
+    /// Writes every item of a `Vec`, array or slice as one transaction.
+    pub fn write_all<S: AsRef<[T]>>(&mut self, items: S) {
+        let mut transaction = self.transaction();
+        for item in items.as_ref() {
+            transaction.write(item);
+        }
+    }
+
+    /// Registers a callback receiving the rows of every successful flush.
+    ///
+    /// The batch is cloned before it is handed to the insert function, so each
+    /// flush pays for one extra copy of the buffer. The callback does not fire
+    /// when the insert fails.
+    #[must_use]
+    pub fn with_committed_rows_callback<C>(mut self, callback: C) -> Self
+    where
+        C: FnMut(&[T]) + Send + 'static,
+    {
+        self.on_commit_rows = Some(RowsCallback {
+            snapshot: <[T]>::to_vec,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Keeps the rows of a failed flush buffered, with the pending counters
+    /// they had, so a later commit inserts them again. Batches a flush already
+    /// inserted are not put back. Like [`with_committed_rows_callback`], this
+    /// keeps a copy of every batch while it is being inserted.
+    ///
+    /// [`with_committed_rows_callback`]: Self::with_committed_rows_callback
+    #[must_use]
+    pub fn with_restore_on_failure(mut self) -> Self {
+        self.keep_on_failure = Some(<[T]>::to_vec);
+        self
+    }
+
+    /// Makes each flush try the insert function up to `attempts` times per
+    /// batch before returning the error, at the same cost as
+    /// [`with_restore_on_failure`]. `0` is treated as `1`.
+    ///
+    /// [`with_restore_on_failure`]: Self::with_restore_on_failure
+    #[must_use]
+    pub fn with_retries(mut self, attempts: u32) -> Self {
+        self.retry = (attempts > 1).then_some(Retry {
+            attempts,
+            copy: <[T]>::to_vec,
+        });
+        self
+    }
+
+    /// Fails an insert that has not completed within `timeout` with
+    /// [`InserterError::Timeout`], so a hung connection cannot stall a flush
+    /// forever. Only available with the `tokio` feature.
+    ///
+    /// The timer starts when the batch is handed to the insert function, and
+    /// every retry from [`with_retries`] gets a fresh one. A timed out flush
+    /// puts its rows back into the buffer for a later commit, so like
+    /// [`with_restore_on_failure`] this keeps a copy of every batch while it
+    /// is being inserted. Flushes must run inside a tokio runtime with time
+    /// enabled.
+    ///
+    /// [`with_retries`]: Self::with_retries
+    /// [`with_restore_on_failure`]: Self::with_restore_on_failure
+    #[cfg(feature = "tokio")]
+    #[must_use]
+    pub fn with_insert_timeout(mut self, timeout: Duration) -> Self {
+        self.insert_timeout = Some(InsertTimeout {
+            timeout,
+            copy: <[T]>::to_vec,
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+// Tests record batches in `inserted` next to the `inserter` itself.
+#[allow(clippy::similar_names)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::fmt;
+    use std::io;
+    use std::rc::Rc;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct TestRow {
+        id: u64,
+    }
+
+    #[test]
+    fn test_basic_insert() {
+        pollster::block_on(async {
+            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
+            let inserted_clone = Rc::clone(&inserted);
+
+            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+                let inserted = Rc::clone(&inserted_clone);
+                async move {
+                    inserted.borrow_mut().push(batch);
+                    Ok::<_, io::Error>(())
+                }
+            })
+            .with_max_rows(2);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+
+            let stats = inserter.commit().await.unwrap();
+            assert_eq!(stats.rows, 2);
+
+            let batches = inserted.borrow();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0].len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_force_commit() {
+        pollster::block_on(async {
+            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
+            let inserted_clone = Rc::clone(&inserted);
+
+            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+                let inserted = Rc::clone(&inserted_clone);
+                async move {
+                    inserted.borrow_mut().push(batch);
+                    Ok::<_, io::Error>(())
+                }
+            })
+            .with_max_rows(100);
+
+            inserter.write(&TestRow { id: 1 });
+
+            let stats = inserter.force_commit().await.unwrap();
+            assert_eq!(stats.rows, 1);
+        });
+    }
+
+    #[test]
+    fn test_end() {
+        pollster::block_on(async {
+            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
+            let inserted_clone = Rc::clone(&inserted);
+
+            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+                let inserted = Rc::clone(&inserted_clone);
+                async move {
+                    inserted.borrow_mut().push(batch);
+                    Ok::<_, io::Error>(())
+                }
+            })
+            .with_max_rows(100);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            inserter.write(&TestRow { id: 3 });
+
+            let stats = inserter.end().await.unwrap();
+            assert_eq!(stats.rows, 3);
+            assert_eq!(stats.transactions, 1);
+        });
+    }
+
+    #[test]
+    fn test_commit_callback() {
+        pollster::block_on(async {
+            let callback_called: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+            let callback_clone = Arc::clone(&callback_called);
+
+            let mut inserter =
+                Inserter::new(|_batch: Vec<TestRow>| async move { Ok::<_, io::Error>(()) })
+                    .with_max_rows(1)
+                    .with_commit_callback(move |_stats| {
+                        *callback_clone.lock().unwrap() = true;
+                    });
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.commit().await.unwrap();
+
+            assert!(*callback_called.lock().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_no_commit_when_below_limit() {
+        pollster::block_on(async {
+            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
+            let inserted_clone = Rc::clone(&inserted);
+
+            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+                let inserted = Rc::clone(&inserted_clone);
+                async move {
+                    inserted.borrow_mut().push(batch);
+                    Ok::<_, io::Error>(())
+                }
+            })
+            .with_max_rows(10);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+
+            let stats = inserter.commit().await.unwrap();
+            assert_eq!(stats.rows, 0);
+
+            let batches = inserted.borrow();
+            assert!(batches.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_has_period() {
+        let inserter = Inserter::new(|_batch: Vec<TestRow>| async move { Ok::<_, io::Error>(()) });
+        assert!(!inserter.has_period());
+
+        let inserter = inserter.with_period(Duration::from_secs(1));
+        assert!(inserter.has_period());
+    }
+
+    #[test]
+    fn test_target_rate_derives_period() {
+        let mut inserter =
+            Inserter::new(|_batch: Vec<TestRow>| async move { Ok::<_, io::Error>(()) })
+                .with_max_rows(1000)
+                .with_target_rate(500.0);
+
+        assert_eq!(inserter.period(), Some(Duration::from_secs(2)));
+
+        inserter.set_max_rows(250);
+        assert_eq!(inserter.period(), Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_target_rate_requires_finite_max_rows() {
+        let inserter =
+            Inserter::new(|_batch: Vec<TestRow>| async move { Ok::<_, io::Error>(()) })
+                .with_target_rate(500.0);
+
+        assert_eq!(inserter.period(), None);
+    }
+
+    #[test]
+    fn test_group_key() {
+        pollster::block_on(async {
+            let received: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
+            let received_clone = Rc::clone(&received);
+
+            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+                let received = Rc::clone(&received_clone);
+                async move {
+                    received.borrow_mut().push(batch);
+                    Ok::<_, io::Error>(())
+                }
+            })
+            .with_max_rows(100)
+            .with_group_key(|row: &TestRow| row.id % 3);
+
+            for id in 0..9 {
+                inserter.write(&TestRow { id });
+            }
+
+            let stats = inserter.force_commit().await.unwrap();
+            assert_eq!(stats.rows, 9);
+            assert_eq!(stats.transactions, 1);
+
+            let batches = received.borrow();
+            assert_eq!(batches.len(), 3);
+            for (key, batch) in batches.iter().enumerate() {
+                assert_eq!(batch.len(), 3);
+                assert!(batch.iter().all(|row| row.id % 3 == key as u64));
+            }
+        });
+    }
+
+    #[derive(Debug)]
+    enum AppError {
+        Storage(io::Error),
+    }
+
+    impl fmt::Display for AppError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Storage(err) => write!(f, "storage: {err}"),
+            }
+        }
+    }
+
+    impl Error for AppError {}
+
+    #[test]
+    fn test_map_err() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::new(|_batch: Vec<TestRow>| async move {
+                Err::<(), _>(io::Error::other("connection reset"))
+            })
+            .with_max_rows(1)
+            .map_err(AppError::Storage);
+
+            inserter.write(&TestRow { id: 1 });
+
+            let err = inserter.commit().await.unwrap_err();
+            let AppError::Storage(source) = err.into_inner();
+            assert_eq!(source.to_string(), "connection reset");
+        });
+    }
+
+    #[test]
+    fn test_committed_rows_callback() {
+        pollster::block_on(async {
+            let committed: Arc<Mutex<Vec<TestRow>>> = Arc::new(Mutex::new(Vec::new()));
+            let committed_clone = Arc::clone(&committed);
+
+            let mut inserter =
+                Inserter::new(|_batch: Vec<TestRow>| async move { Ok::<_, io::Error>(()) })
+                    .with_max_rows(2)
+                    .with_committed_rows_callback(move |rows| {
+                        committed_clone.lock().unwrap().extend_from_slice(rows);
+                    });
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.commit().await.unwrap();
+            assert!(committed.lock().unwrap().is_empty());
+
+            inserter.write(&TestRow { id: 2 });
+            inserter.commit().await.unwrap();
+            assert_eq!(
+                *committed.lock().unwrap(),
+                vec![TestRow { id: 1 }, TestRow { id: 2 }]
+            );
+        });
+    }
+
+    #[test]
+    fn test_committed_rows_callback_skipped_on_failure() {
+        pollster::block_on(async {
+            let called = Arc::new(Mutex::new(false));
+            let called_clone = Arc::clone(&called);
+
+            let mut inserter = Inserter::new(|_batch: Vec<TestRow>| async move {
+                Err::<(), _>(io::Error::other("down"))
+            })
+            .with_committed_rows_callback(move |_rows| {
+                *called_clone.lock().unwrap() = true;
+            });
+
+            inserter.write(&TestRow { id: 1 });
+            assert!(inserter.force_commit().await.is_err());
+            assert!(!*called.lock().unwrap());
+        });
+    }
+
+    #[test]
+    fn test_retries() {
+        pollster::block_on(async {
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let calls_clone = Arc::clone(&calls);
+            let commits = Arc::new(Mutex::new(0));
+            let commits_clone = Arc::clone(&commits);
+
+            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+                let attempt = {
+                    let mut calls = calls_clone.lock().unwrap();
+                    calls.push(batch.len());
+                    calls.len()
+                };
+                let result = if attempt < 3 {
+                    Err(io::Error::other("blip"))
+                } else {
+                    Ok(())
+                };
+                async move { result }
+            })
+            .with_retries(3)
+            .with_commit_callback(move |_| *commits_clone.lock().unwrap() += 1);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 2);
+            assert_eq!(*calls.lock().unwrap(), vec![2, 2, 2]);
+            assert_eq!(*commits.lock().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_error_callback() {
+        pollster::block_on(async {
+            let failures = Arc::new(Mutex::new(Vec::new()));
+            let failures_clone = Arc::clone(&failures);
+            let mut inserter = Inserter::new(|_batch: Vec<TestRow>| async move {
+                Err::<(), _>(io::Error::other("down"))
+            })
+            .with_retries(2)
+            .with_error_callback(move |err, failed| {
+                failures_clone
+                    .lock()
+                    .unwrap()
+                    .push((err.to_string(), failed.rows));
+            });
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            assert!(inserter.force_commit().await.is_err());
+            assert_eq!(*failures.lock().unwrap(), vec![("down".to_owned(), 2)]);
+        });
+    }
+
+    #[test]
+    fn test_idempotency_key_is_stable_across_retries() {
+        pollster::block_on(async {
+            let keys = Arc::new(Mutex::new(Vec::new()));
+            let keys_clone = Arc::clone(&keys);
+
+            let mut inserter = Inserter::new_idempotent(
+                move |_batch: Vec<TestRow>, key: (u64, u64)| {
+                    let attempt = {
+                        let mut keys = keys_clone.lock().unwrap();
+                        keys.push(key);
+                        keys.len()
+                    };
+                    let result = if attempt == 1 || attempt == 2 {
+                        Err(io::Error::other("timeout"))
+                    } else {
+                        Ok(())
+                    };
+                    async move { result }
+                },
+                |batch, sequence| (sequence, batch.iter().map(|row| row.id).sum()),
+            )
+            .with_retries(3);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            inserter.force_commit().await.unwrap();
+            inserter.write(&TestRow { id: 3 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(*keys.lock().unwrap(), vec![(0, 3), (0, 3), (0, 3), (1, 3)]);
+        });
+    }
+
+    #[test]
+    fn test_idempotency_key_is_stable_across_concurrent_retries() {
+        pollster::block_on(async {
+            let keys = Arc::new(Mutex::new(Vec::new()));
+            let keys_clone = Arc::clone(&keys);
+
+            let mut inserter = Inserter::new_idempotent(
+                move |batch: Vec<TestRow>, sequence: u64| {
+                    let first_try = {
+                        let mut keys = keys_clone.lock().unwrap();
+                        keys.push((batch[0].id, sequence));
+                        keys.len() == 1
+                    };
+                    let mut yielded = false;
+                    std::future::poll_fn(move |cx| {
+                        if !std::mem::replace(&mut yielded, true) {
+                            cx.waker().wake_by_ref();
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(if first_try {
+                            Err(io::Error::other("timeout"))
+                        } else {
+                            Ok(())
+                        })
+                    })
+                },
+                |_batch, sequence| sequence,
+            )
+            .with_chunk_size(1)
+            .with_flush_concurrency(3)
+            .with_retries(2);
+            for id in 0..3 {
+                inserter.write(&TestRow { id });
+            }
+
+            inserter.force_commit().await.unwrap();
+            assert_eq!(*keys.lock().unwrap(), vec![(0, 0), (1, 1), (2, 2), (0, 0)]);
+        });
+    }
+
+    #[test]
+    fn test_idempotency_key_is_kept_for_restored_batches() {
+        pollster::block_on(async {
+            let keys = Arc::new(Mutex::new(Vec::new()));
+            let keys_clone = Arc::clone(&keys);
+
+            let mut inserter = Inserter::new_idempotent(
+                move |_batch: Vec<TestRow>, sequence: u64| {
+                    let first_try = {
+                        let mut keys = keys_clone.lock().unwrap();
+                        keys.push(sequence);
+                        keys.len() == 1
+                    };
+                    let result = if first_try {
+                        Err(io::Error::other("timeout"))
+                    } else {
+                        Ok(())
+                    };
+                    async move { result }
+                },
+                |_batch, sequence| sequence,
+            )
+            .with_restore_on_failure();
+
+            inserter.write(&TestRow { id: 1 });
+            assert!(inserter.force_commit().await.is_err());
+            inserter.force_commit().await.unwrap();
+            inserter.write(&TestRow { id: 2 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(*keys.lock().unwrap(), vec![0, 0, 1]);
+        });
+    }
+
+    #[test]
+    fn test_chunk_size() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let commits = Arc::new(Mutex::new(Vec::new()));
+            let commits_clone = Arc::clone(&commits);
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_chunk_size(2)
+                .with_commit_callback(move |q| commits_clone.lock().unwrap().push(q.rows));
+            for id in 0..5 {
+                inserter.write(&TestRow { id });
+            }
+
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 5);
+            let sizes: Vec<usize> = sink.batches().iter().map(Vec::len).collect();
+            assert_eq!(sizes, vec![2, 2, 1]);
+            assert_eq!(*commits.lock().unwrap(), vec![5]);
+            assert_eq!(inserter.committed().rows, 5);
+        });
+    }
+
+    #[test]
+    fn test_commit_per_chunk() {
+        pollster::block_on(async {
+            let commits = Arc::new(Mutex::new(Vec::new()));
+            let commits_clone = Arc::clone(&commits);
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_chunk_size(2)
+                .with_commit_per_chunk(true)
+                .with_commit_callback(move |q| {
+                    commits_clone.lock().unwrap().push((q.rows, q.transactions));
+                });
+            for id in 0..5 {
+                inserter.write(&TestRow { id });
+            }
+
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 5);
+            assert_eq!(*commits.lock().unwrap(), vec![(2, 0), (2, 0), (1, 1)]);
+        });
+    }
+
+    #[test]
+    fn test_chunk_failure_keeps_unsent_chunks() {
+        pollster::block_on(async {
+            let calls = Arc::new(Mutex::new(0));
+            let calls_clone = Arc::clone(&calls);
+            let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+                let call = {
+                    let mut calls = calls_clone.lock().unwrap();
+                    *calls += 1;
+                    *calls
+                };
+                let result = if call == 2 {
+                    Err(io::Error::other("too large"))
+                } else {
+                    Ok(())
+                };
+                async move { result }
+            })
+            .with_chunk_size(2);
+            for id in 0..5 {
+                inserter.write(&TestRow { id });
+            }
+
+            assert!(inserter.force_commit().await.is_err());
+            assert_eq!(inserter.committed().rows, 2);
+            assert_eq!(inserter.buffered(), [TestRow { id: 4 }]);
+            assert_eq!(inserter.pending().rows, 1);
+
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 1);
+            assert_eq!(inserter.committed().rows, 3);
+        });
+    }
+
+    #[test]
+    fn test_flush_concurrency() {
+        let max_active = |concurrency| {
+            let active = Arc::new(AtomicU64::new(0));
+            let max = Arc::new(AtomicU64::new(0));
+            let (active_clone, max_clone) = (Arc::clone(&active), Arc::clone(&max));
+            let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+                let (active, max) = (Arc::clone(&active_clone), Arc::clone(&max_clone));
+                let mut yielded = false;
+                max.fetch_max(active.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                std::future::poll_fn(move |cx| {
+                    if !std::mem::replace(&mut yielded, true) {
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    Poll::Ready(Ok::<_, io::Error>(()))
+                })
+            })
+            .with_chunk_size(1)
+            .with_flush_concurrency(concurrency);
+            for id in 0..5 {
+                inserter.write(&TestRow { id });
+            }
+
+            let flushed = pollster::block_on(inserter.force_commit()).unwrap();
+            assert_eq!(flushed.rows, 5);
+            assert_eq!(inserter.committed().rows, 5);
+            max.load(Ordering::SeqCst)
+        };
+
+        assert_eq!(max_active(1), 1);
+        assert_eq!(max_active(2), 2);
+        assert_eq!(max_active(8), 5);
+    }
+
+    #[test]
+    fn test_ordered_flush() {
+        let commits = |ordered| {
+            let commits = Arc::new(Mutex::new(Vec::new()));
+            let commits_clone = Arc::clone(&commits);
+            // The first batch takes the longest and the last one the least.
+            let mut inserter = Inserter::new(|batch: Vec<TestRow>| {
+                let mut delay = 3 - batch[0].id;
+                std::future::poll_fn(move |cx| {
+                    if delay == 0 {
+                        return Poll::Ready(Ok::<_, io::Error>(()));
+                    }
+                    delay -= 1;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                })
+            })
+            .with_size_fn(|row: &TestRow| usize::try_from(row.id).unwrap())
+            .with_chunk_size(1)
+            .with_flush_concurrency(3)
+            .with_ordered_flush(ordered)
+            .with_commit_per_chunk(true)
+            .with_commit_callback(move |chunk| commits_clone.lock().unwrap().push(chunk.bytes));
+            for id in 1..=3 {
+                inserter.write(&TestRow { id });
+            }
+
+            let flushed = pollster::block_on(inserter.force_commit()).unwrap();
+            assert_eq!(flushed.rows, 3);
+            assert_eq!(inserter.committed().bytes, 6);
+            drop(inserter);
+            Arc::try_unwrap(commits).unwrap().into_inner().unwrap()
+        };
+
+        assert_eq!(commits(false), [3, 2, 1]);
+        assert_eq!(commits(true), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_ordered_flush_failure() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::new(|batch: Vec<TestRow>| {
+                let id = batch[0].id;
+                let mut yielded = false;
+                std::future::poll_fn(move |cx| {
+                    if id == 1 && !std::mem::replace(&mut yielded, true) {
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                    Poll::Ready(if id == 1 {
+                        Err(io::Error::other("down"))
+                    } else {
+                        Ok(())
+                    })
+                })
+            })
+            .with_chunk_size(1)
+            .with_flush_concurrency(3)
+            .with_ordered_flush(true)
+            .with_restore_on_failure();
+            for id in 1..=3 {
+                inserter.write(&TestRow { id });
+            }
+
+            assert!(inserter.force_commit().await.is_err());
+            assert_eq!(inserter.committed().rows, 2);
+            assert_eq!(inserter.buffered(), [TestRow { id: 1 }]);
+        });
+    }
+
+    #[test]
+    fn test_flush_in_flight() {
+        let release = Arc::new(AtomicBool::new(false));
+        let release_clone = Arc::clone(&release);
+        let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+            let release = Arc::clone(&release_clone);
+            std::future::poll_fn(move |_cx| {
+                if release.load(Ordering::SeqCst) {
+                    Poll::Ready(Ok::<_, io::Error>(()))
+                } else {
+                    Poll::Pending
+                }
+            })
+        })
+        .with_max_rows(5)
+        .with_chunk_size(1)
+        .with_flush_concurrency(3);
+        for id in 0..5 {
+            inserter.write(&TestRow { id });
+        }
+        assert_eq!(inserter.flush_in_flight(), 0);
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        assert!(inserter.poll_commit(&mut cx).is_pending());
+        assert_eq!(inserter.flush_in_flight(), 3);
+        assert_eq!(inserter.max_in_flight(), 3);
+
+        release.store(true, Ordering::SeqCst);
+        let Poll::Ready(flushed) = inserter.poll_commit(&mut cx) else {
+            panic!("flush should complete once released");
+        };
+        assert_eq!(flushed.unwrap().rows, 5);
+        assert_eq!(inserter.flush_in_flight(), 0);
+        assert_eq!(inserter.max_in_flight(), 3);
+    }
+
+    #[test]
+    fn test_flush_concurrency_failure() {
+        pollster::block_on(async {
+            let calls = Arc::new(AtomicU64::new(0));
+            let calls_clone = Arc::clone(&calls);
+            let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+                let result = if calls_clone.fetch_add(1, Ordering::SeqCst) == 1 {
+                    Err(io::Error::other("down"))
+                } else {
+                    Ok(())
+                };
+                std::future::ready(result)
+            })
+            .with_chunk_size(1)
+            .with_flush_concurrency(2);
+            for id in 0..4 {
+                inserter.write(&TestRow { id });
+            }
+
+            assert!(inserter.force_commit().await.is_err());
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            assert_eq!(inserter.committed().rows, 1);
+            assert_eq!(inserter.buffered(), [TestRow { id: 2 }, TestRow { id: 3 }]);
+        });
+    }
+
+    #[test]
+    fn test_retries_exhausted() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::new(|_batch: Vec<TestRow>| async move {
+                Err::<(), _>(io::Error::other("down"))
+            })
+            .with_retries(2);
+
+            inserter.write(&TestRow { id: 1 });
+            let err = inserter.force_commit().await.unwrap_err();
+            assert_eq!(err.attempts(), 2);
+            assert_eq!(err.to_string(), "inserter error after 2 attempts: down");
+        });
+    }
+
+    #[test]
+    fn test_extra_counter() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_max_rows(2)
+                .with_extra_counter(|payload: &mut u64, row: &TestRow| *payload += row.id * 10);
+
+            inserter.write(&TestRow { id: 1 });
+            let (flushed, extra) = inserter.commit().await.unwrap();
+            assert!(flushed.is_empty());
+            assert_eq!(extra, 0);
+
+            inserter.write(&TestRow { id: 2 });
+            let (flushed, extra) = inserter.commit().await.unwrap();
+            assert_eq!((flushed.rows, extra), (2, 30));
+
+            inserter.write(&TestRow { id: 4 });
+            let (flushed, extra) = inserter.force_commit().await.unwrap();
+            assert_eq!((flushed.rows, extra), (1, 40));
+
+            inserter.write(&TestRow { id: 5 });
+            let (committed, extra) = inserter.end().await.unwrap();
+            assert_eq!((committed.rows, extra), (4, 50));
+        });
+    }
+
+    #[test]
+    fn test_extra_counter_skips_failed_flushes() {
+        pollster::block_on(async {
+            let fail = Arc::new(AtomicBool::new(true));
+            let fail_clone = Arc::clone(&fail);
+            let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+                std::future::ready(if fail_clone.swap(false, Ordering::SeqCst) {
+                    Err(io::Error::other("down"))
+                } else {
+                    Ok(())
+                })
+            })
+            .with_restore_on_failure()
+            .with_extra_counter(|count: &mut u64, _row: &TestRow| *count += 1);
+
+            inserter.write(&TestRow { id: 1 });
+            assert!(inserter.force_commit().await.is_err());
+            inserter.write(&TestRow { id: 2 });
+            let (flushed, extra) = inserter.force_commit().await.unwrap();
+            assert_eq!((flushed.rows, extra), (2, 2));
+        });
+    }
+
+    #[test]
+    fn test_restore_on_failure() {
+        pollster::block_on(async {
+            let fail = Arc::new(Mutex::new(true));
+            let fail_clone = Arc::clone(&fail);
+            let batches = Arc::new(Mutex::new(Vec::new()));
+            let batches_clone = Arc::clone(&batches);
+            let commits = Arc::new(Mutex::new(0));
+            let commits_clone = Arc::clone(&commits);
 
             let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
-                let inserted = Rc::clone(&inserted_clone);
+                let result = if std::mem::take(&mut *fail_clone.lock().unwrap()) {
+                    Err(io::Error::other("down"))
+                } else {
+                    batches_clone.lock().unwrap().push(batch);
+                    Ok(())
+                };
+                async move { result }
+            })
+            .with_restore_on_failure()
+            .with_commit_callback(move |_| *commits_clone.lock().unwrap() += 1);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            assert!(inserter.force_commit().await.is_err());
+            assert_eq!(inserter.pending().rows, 2);
+            assert_eq!(inserter.pending().transactions, 1);
+            assert_eq!(*commits.lock().unwrap(), 0);
+
+            inserter.write(&TestRow { id: 3 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 3);
+            assert_eq!(
+                *batches.lock().unwrap(),
+                vec![vec![
+                    TestRow { id: 1 },
+                    TestRow { id: 2 },
+                    TestRow { id: 3 }
+                ]]
+            );
+            assert_eq!(*commits.lock().unwrap(), 1);
+            assert!(inserter.pending().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_period_with_mock_clock() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_period(Duration::from_secs(5))
+                .with_clock(clock.clone());
+
+            inserter.write(&TestRow { id: 1 });
+            clock.advance(Duration::from_secs(4));
+            assert!(inserter.commit().await.unwrap().is_empty());
+            assert_eq!(inserter.time_left(), Some(Duration::from_secs(1)));
+
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(inserter.commit().await.unwrap().rows, 1);
+            assert_eq!(sink.batches(), vec![vec![TestRow { id: 1 }]]);
+        });
+    }
+
+    #[cfg(feature = "period_bias")]
+    #[test]
+    fn test_seeded_bias() {
+        let clock = crate::test_util::MockClock::new();
+        let time_left = |seed| {
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_period(Duration::from_secs(10))
+                .with_seeded_bias(0.5, seed)
+                .with_clock(clock.clone());
+            inserter.write(&TestRow { id: 1 });
+            inserter.time_left().unwrap()
+        };
+
+        assert_eq!(time_left(3), time_left(3));
+        assert_ne!(time_left(3), time_left(4));
+        assert!(time_left(3) >= Duration::from_secs(5) && time_left(3) <= Duration::from_secs(15));
+    }
+
+    #[cfg(feature = "period_bias")]
+    #[test]
+    fn test_sampling() {
+        let kept = |rate| {
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_rng_seed(7)
+                .with_sampling(rate);
+            for id in 0..10_000 {
+                inserter.write(&TestRow { id });
+            }
+            assert_eq!(inserter.buffer_len() as u64 + inserter.dropped(), 10_000);
+            inserter.buffer_len()
+        };
+
+        assert_eq!(kept(0.0), 0);
+        assert_eq!(kept(1.0), 10_000);
+        assert!((2_300..=2_700).contains(&kept(0.25)), "{}", kept(0.25));
+        assert_eq!(kept(0.25), kept(0.25));
+    }
+
+    #[test]
+    fn test_min_rows() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_max_rows(10)
+                .with_min_rows(2)
+                .with_period(Duration::from_secs(5))
+                .with_clock(clock.clone());
+
+            inserter.write(&TestRow { id: 1 });
+            clock.advance(Duration::from_secs(5));
+            assert!(inserter.commit().await.unwrap().is_empty());
+
+            inserter.write(&TestRow { id: 2 });
+            assert_eq!(inserter.commit().await.unwrap().rows, 2);
+
+            inserter.write(&TestRow { id: 3 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 1);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "min rows 5 exceed max rows 4")]
+    fn test_min_rows_above_max_rows() {
+        let _ = Inserter::<TestRow>::null()
+            .with_max_rows(4)
+            .with_min_rows(5);
+    }
+
+    #[test]
+    fn test_next_flush_at() {
+        let clock = crate::test_util::MockClock::new();
+        let mut inserter = Inserter::<TestRow>::null()
+            .with_period(Duration::from_secs(5))
+            .with_clock(clock.clone());
+        assert_eq!(inserter.next_flush_at(), None);
+
+        let before = clock.now();
+        inserter.write(&TestRow { id: 1 });
+        assert_eq!(
+            inserter.next_flush_at(),
+            Some(before + Duration::from_secs(5))
+        );
+    }
+
+    #[test]
+    fn test_schedule_miss_callback() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let slow_clock = clock.clone();
+            let misses = Arc::new(Mutex::new(Vec::new()));
+            let misses_clone = Arc::clone(&misses);
+            let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+                slow_clock.advance(Duration::from_secs(12));
+                async { Ok::<_, Infallible>(()) }
+            })
+            .with_period(Duration::from_secs(10))
+            .with_schedule(Schedule::FixedRate)
+            .with_schedule_miss_callback(move |missed_by| {
+                misses_clone.lock().unwrap().push(missed_by);
+            })
+            .with_clock(clock.clone());
+
+            inserter.write(&TestRow { id: 1 });
+            clock.advance(Duration::from_secs(10));
+            assert_eq!(inserter.commit().await.unwrap().rows, 1);
+            assert_eq!(*misses.lock().unwrap(), vec![Duration::from_secs(2)]);
+            assert_eq!(inserter.time_left(), Some(Duration::from_secs(8)));
+        });
+    }
+
+    #[test]
+    fn test_flush_keeps_period_timer() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_period(Duration::from_secs(5))
+                .with_clock(clock.clone());
+
+            inserter.write(&TestRow { id: 1 });
+            clock.advance(Duration::from_secs(3));
+            assert_eq!(inserter.flush().await.unwrap().rows, 1);
+            assert_eq!(inserter.time_left(), Some(Duration::from_secs(2)));
+
+            inserter.write(&TestRow { id: 2 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(inserter.time_left(), Some(Duration::from_secs(5)));
+        });
+    }
+
+    struct YieldOnce {
+        yielded: bool,
+    }
+
+    impl Future for YieldOnce {
+        type Output = Result<(), io::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if self.yielded {
+                Poll::Ready(Ok(()))
+            } else {
+                self.yielded = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    #[test]
+    fn test_poll_commit() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let mut calls = 0;
+
+        let mut inserter = Inserter::new(|_batch: Vec<TestRow>| {
+            calls += 1;
+            YieldOnce { yielded: false }
+        })
+        .with_max_rows(2);
+
+        inserter.write(&TestRow { id: 1 });
+        assert!(matches!(
+            inserter.poll_commit(&mut cx),
+            Poll::Ready(Ok(stats)) if stats.is_empty()
+        ));
+
+        inserter.write(&TestRow { id: 2 });
+        assert!(inserter.poll_commit(&mut cx).is_pending());
+
+        let Poll::Ready(Ok(stats)) = inserter.poll_commit(&mut cx) else {
+            panic!("flush should complete on the second poll");
+        };
+        assert_eq!(stats.rows, 2);
+        assert!(inserter.pending().is_empty());
+
+        drop(inserter);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_writes_during_flush_are_not_lost() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let batches = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&batches);
+
+        let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+            recorded
+                .borrow_mut()
+                .push(batch.iter().map(|row| row.id).collect::<Vec<_>>());
+            YieldOnce { yielded: false }
+        })
+        .with_max_rows(1);
+
+        inserter.write(&TestRow { id: 1 });
+        assert!(inserter.poll_commit(&mut cx).is_pending());
+        inserter.write(&TestRow { id: 2 });
+        {
+            let mut force_commit = std::pin::pin!(inserter.force_commit());
+            assert!(force_commit.as_mut().poll(&mut cx).is_pending());
+            let Poll::Ready(Ok(stats)) = force_commit.as_mut().poll(&mut cx) else {
+                panic!("force_commit should complete both flushes");
+            };
+            assert_eq!(stats.rows, 2);
+        }
+
+        inserter.write(&TestRow { id: 3 });
+        assert!(inserter.poll_commit(&mut cx).is_pending());
+        inserter.write(&TestRow { id: 4 });
+        inserter.write(&TestRow { id: 5 });
+        let mut end = std::pin::pin!(inserter.end());
+        let stats = loop {
+            if let Poll::Ready(result) = end.as_mut().poll(&mut cx) {
+                break result.unwrap();
+            }
+        };
+        assert_eq!(stats.rows, 5);
+        assert_eq!(
+            *batches.borrow(),
+            vec![vec![1], vec![2], vec![3], vec![4, 5]]
+        );
+    }
+
+    #[test]
+    fn test_cross_batch_dedup_ttl() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_cross_batch_dedup(|row: &TestRow| row.id, 100, Duration::from_secs(30))
+                .with_clock(clock.clone());
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.force_commit().await.unwrap();
+
+            inserter.write(&TestRow { id: 1 });
+            assert_eq!(inserter.pending().rows, 0);
+            assert_eq!(inserter.dropped(), 1);
+
+            clock.advance(Duration::from_secs(31));
+            inserter.write(&TestRow { id: 1 });
+            assert_eq!(inserter.pending().rows, 1);
+            assert_eq!(inserter.dropped(), 1);
+        });
+    }
+
+    #[test]
+    fn test_cross_batch_dedup_forgets_overflowed_rows() {
+        pollster::block_on(async {
+            for policy in [OverflowPolicy::DropNewest, OverflowPolicy::DropOldest] {
+                let sink = crate::test_util::RecordingSink::new();
+                let mut inserter = Inserter::new(sink.insert_fn())
+                    .with_cross_batch_dedup(|row: &TestRow| row.id, 100, Duration::from_secs(30))
+                    .with_buffer_limit(1)
+                    .with_overflow_policy(policy);
+
+                inserter.write(&TestRow { id: 1 });
+                inserter.write(&TestRow { id: 2 });
+                assert_eq!(inserter.dropped(), 1);
+                inserter.force_commit().await.unwrap();
+
+                // Whichever row overflow dropped can be sent again.
+                let lost = if policy == OverflowPolicy::DropNewest {
+                    2
+                } else {
+                    1
+                };
+                inserter.write(&TestRow { id: lost });
+                assert_eq!(inserter.pending().rows, 1);
+                assert_eq!(inserter.dropped(), 1);
+            }
+        });
+    }
+
+    #[test]
+    fn test_write_priority() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_max_rows(100);
+
+            for id in 1..=3 {
+                inserter.write(&TestRow { id });
+            }
+            inserter.write_priority(TestRow { id: 99 });
+
+            let stats = inserter.commit().await.unwrap();
+            assert_eq!(stats.rows, 4);
+
+            let batches = sink.batches();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(
+                batches[0].iter().map(|row| row.id).collect::<Vec<_>>(),
+                vec![99, 1, 2, 3]
+            );
+
+            inserter.write(&TestRow { id: 4 });
+            assert!(inserter.commit().await.unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_disarm_keeps_row_limit() {
+        let clock = crate::test_util::MockClock::new();
+        let sink = crate::test_util::RecordingSink::new();
+
+        let mut inserter = Inserter::new(sink.insert_fn())
+            .with_max_rows(3)
+            .with_period(Duration::from_secs(1))
+            .with_clock(clock.clone());
+
+        inserter.write(&TestRow { id: 1 });
+        inserter.disarm();
+        clock.advance(Duration::from_secs(2));
+        assert!(!inserter.is_due());
+        assert!(inserter.time_left().is_none());
+
+        inserter.write(&TestRow { id: 2 });
+        inserter.write(&TestRow { id: 3 });
+        assert!(inserter.is_due());
+    }
+
+    #[test]
+    fn test_arm_restarts_period() {
+        let clock = crate::test_util::MockClock::new();
+        let sink = crate::test_util::RecordingSink::new();
+
+        let mut inserter = Inserter::new(sink.insert_fn())
+            .with_period(Duration::from_secs(1))
+            .with_clock(clock.clone());
+
+        inserter.disarm();
+        inserter.write(&TestRow { id: 1 });
+        clock.advance(Duration::from_secs(2));
+        assert!(!inserter.is_due());
+
+        inserter.arm();
+        assert!(!inserter.is_due());
+        clock.advance(Duration::from_secs(1));
+        assert!(inserter.is_due());
+    }
+
+    #[test]
+    fn test_time_bucketing_flushes_closed_bucket() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let start = clock.now();
+            let sink = crate::test_util::RecordingSink::new();
+
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_clock(clock.clone())
+                .with_time_bucketing(Duration::from_secs(1), move |row: &TestRow| {
+                    start + Duration::from_millis(row.id)
+                });
+
+            for id in [0, 1200, 400, 1500] {
+                inserter.write(&TestRow { id });
+            }
+            assert!(!inserter.is_due());
+
+            clock.advance(Duration::from_millis(1300));
+            assert!(inserter.is_due());
+
+            let stats = inserter.commit().await.unwrap();
+            assert_eq!(stats.rows, 2);
+            assert_eq!(inserter.pending().rows, 2);
+            assert_eq!(
+                sink.take(),
+                vec![vec![TestRow { id: 0 }, TestRow { id: 400 }]]
+            );
+            assert!(!inserter.is_due());
+
+            clock.advance(Duration::from_secs(1));
+            let stats = inserter.commit().await.unwrap();
+            assert_eq!(stats.rows, 2);
+            assert_eq!(
+                sink.take(),
+                vec![vec![TestRow { id: 1200 }, TestRow { id: 1500 }]]
+            );
+
+            let total = inserter.end().await.unwrap();
+            assert_eq!(total.rows, 4);
+            assert_eq!(total.transactions, 1);
+        });
+    }
+
+    /// Snaps deadlines to the next multiple of `every` after `origin`.
+    #[derive(Debug)]
+    struct Boundaries {
+        origin: Instant,
+        every: Duration,
+    }
+
+    impl Scheduler for Boundaries {
+        fn next_deadline(&self, now: Instant) -> Option<Instant> {
+            let elapsed = now.duration_since(self.origin).as_nanos();
+            let every = self.every.as_nanos();
+            let next = (elapsed / every + 1) * every;
+            Some(self.origin + Duration::from_nanos(u64::try_from(next).ok()?))
+        }
+    }
+
+    #[test]
+    fn test_custom_scheduler_snaps_to_boundaries() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_clock(clock.clone())
+                .with_scheduler(Boundaries {
+                    origin: clock.now(),
+                    every: Duration::from_secs(5),
+                });
+
+            clock.advance(Duration::from_secs(3));
+            inserter.write(&TestRow { id: 1 });
+            assert_eq!(inserter.time_left(), Some(Duration::from_secs(2)));
+
+            clock.advance(Duration::from_secs(2));
+            assert_eq!(inserter.commit().await.unwrap().rows, 1);
+            assert_eq!(inserter.time_left(), Some(Duration::from_secs(5)));
+
+            clock.advance(Duration::from_secs(1));
+            inserter.write(&TestRow { id: 2 });
+            assert_eq!(inserter.time_left(), Some(Duration::from_secs(4)));
+        });
+    }
+
+    #[test]
+    fn test_per_batch_resource() {
+        pollster::block_on(async {
+            let checkouts = Rc::new(RefCell::new(0_u32));
+            let checkouts_clone = Rc::clone(&checkouts);
+            let used: Rc<RefCell<Vec<(u32, usize)>>> = Rc::new(RefCell::new(Vec::new()));
+            let used_clone = Rc::clone(&used);
+
+            let mut inserter = Inserter::new(crate::per_batch_resource(
+                move || {
+                    *checkouts_clone.borrow_mut() += 1;
+                    let connection = *checkouts_clone.borrow();
+                    async move { Ok::<_, io::Error>(connection) }
+                },
+                move |connection: u32, batch: Vec<TestRow>| {
+                    used_clone.borrow_mut().push((connection, batch.len()));
+                    async move { Ok(()) }
+                },
+            ))
+            .with_max_rows(2);
+
+            for id in 0..4 {
+                inserter.write(&TestRow { id });
+                inserter.commit().await.unwrap();
+            }
+
+            assert_eq!(*checkouts.borrow(), 2);
+            assert_eq!(*used.borrow(), vec![(1, 2), (2, 2)]);
+        });
+    }
+
+    #[test]
+    fn test_per_batch_resource_fn_mut() {
+        pollster::block_on(async {
+            let mut inserts = Vec::new();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let mut inserter = Inserter::new(crate::per_batch_resource(
+                || async { Ok::<_, io::Error>(()) },
+                move |(), batch: Vec<TestRow>| {
+                    inserts.push(batch.len());
+                    sender.send(inserts.clone()).unwrap();
+                    async move { Ok(()) }
+                },
+            ));
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.force_commit().await.unwrap();
+            inserter.write(&TestRow { id: 2 });
+            inserter.write(&TestRow { id: 3 });
+            inserter.force_commit().await.unwrap();
+
+            assert_eq!(receiver.try_iter().last(), Some(vec![1, 2]));
+        });
+    }
+
+    #[test]
+    fn test_flush_count() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_max_rows(3);
+
+            for id in 0..7 {
+                inserter.write(&TestRow { id });
+                inserter.commit().await.unwrap();
+            }
+            assert_eq!(inserter.flush_count(), 2);
+
+            inserter.force_commit().await.unwrap();
+            inserter.force_commit().await.unwrap();
+            assert_eq!(inserter.flush_count(), 3);
+            assert_eq!(sink.batch_count(), 3);
+        });
+    }
+
+    #[test]
+    fn test_commit_flushed() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_max_rows(2)
+                .with_pre_commit_callback(|batch, _| batch[0].id != 2);
+
+            assert!(!inserter.commit_flushed().await.unwrap());
+            inserter.write(&TestRow { id: 0 });
+            assert!(!inserter.commit_flushed().await.unwrap());
+            inserter.write(&TestRow { id: 1 });
+            assert!(inserter.commit_flushed().await.unwrap());
+
+            inserter.write(&TestRow { id: 2 });
+            inserter.write(&TestRow { id: 3 });
+            assert!(!inserter.commit_flushed().await.unwrap());
+            assert_eq!(inserter.buffer_len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_try_write_many() {
+        let sink = crate::test_util::RecordingSink::new();
+        let mut inserter = Inserter::new(sink.insert_fn());
+
+        let written = inserter.try_write_many((0..3).map(|id| Ok::<_, &str>(TestRow { id })));
+        assert_eq!(written, Ok(3));
+
+        let items = vec![
+            Ok(TestRow { id: 3 }),
+            Err("bad line"),
+            Ok(TestRow { id: 4 }),
+        ];
+        assert_eq!(inserter.try_write_many(items), Err("bad line"));
+        assert_eq!(inserter.pending().rows, 4);
+    }
+
+    #[test]
+    fn test_transaction_guard() {
+        let sink = crate::test_util::RecordingSink::new();
+        let mut inserter = Inserter::new(sink.insert_fn());
+
+        inserter.write(&TestRow { id: 0 });
+        for id in 1..10 {
+            let mut tx = inserter.transaction();
+            tx.write(&TestRow { id });
+            if id == 2 {
+                break;
+            }
+            tx.write_owned(TestRow { id: id + 100 });
+        }
+        inserter.write(&TestRow { id: 3 });
+
+        assert_eq!(inserter.pending().rows, 5);
+        assert_eq!(inserter.pending().transactions, 4);
+    }
+
+    #[test]
+    fn test_extract_matching() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn());
+
+            for id in 0..6 {
+                inserter.write(&TestRow { id });
+            }
+            inserter.write_priority(TestRow { id: 10 });
+
+            let extracted = inserter.extract_matching(|row| row.id % 2 == 0);
+            let ids: Vec<u64> = extracted.iter().map(|row| row.id).collect();
+            assert_eq!(ids, vec![10, 0, 2, 4]);
+            assert_eq!(inserter.pending().rows, 3);
+
+            inserter.force_commit().await.unwrap();
+            let ids: Vec<u64> = sink.take().concat().iter().map(|row| row.id).collect();
+            assert_eq!(ids, vec![1, 3, 5]);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_insert_semaphore_serializes_flushes() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let semaphore = Arc::new(Semaphore::new(1));
+        let started = Rc::new(RefCell::new(Vec::new()));
+
+        let make_inserter = |name: &'static str| {
+            let started = Rc::clone(&started);
+            Inserter::new(move |_batch: Vec<TestRow>| {
+                started.borrow_mut().push(name);
+                YieldOnce { yielded: false }
+            })
+            .with_insert_semaphore(Arc::clone(&semaphore))
+        };
+        let mut first = make_inserter("first");
+        let mut second = make_inserter("second");
+        first.write(&TestRow { id: 1 });
+        second.write(&TestRow { id: 2 });
+
+        assert!(first.poll_force_commit(&mut cx).is_pending());
+        assert!(second.poll_force_commit(&mut cx).is_pending());
+        assert_eq!(*started.borrow(), vec!["first"]);
+
+        assert!(first.poll_force_commit(&mut cx).is_ready());
+        assert!(second.poll_force_commit(&mut cx).is_pending());
+        assert_eq!(*started.borrow(), vec!["first", "second"]);
+        assert!(second.poll_force_commit(&mut cx).is_ready());
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_commit_on_notify() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let notify = Arc::new(Notify::new());
+        let first_sink = crate::test_util::RecordingSink::new();
+        let second_sink = crate::test_util::RecordingSink::new();
+        let mut first =
+            Inserter::new(first_sink.insert_fn()).with_flush_notify(Arc::clone(&notify));
+        let mut second =
+            Inserter::new(second_sink.insert_fn()).with_flush_notify(Arc::clone(&notify));
+        first.write(&TestRow { id: 1 });
+        second.write(&TestRow { id: 2 });
+
+        {
+            let mut first_cut = std::pin::pin!(first.commit_on_notify());
+            assert!(first_cut.as_mut().poll(&mut cx).is_pending());
+            notify.notify_waiters();
+            assert!(first_cut.as_mut().poll(&mut cx).is_ready());
+        }
+        {
+            let mut second_cut = std::pin::pin!(second.commit_on_notify());
+            assert!(second_cut.as_mut().poll(&mut cx).is_ready());
+        }
+
+        assert_eq!(first_sink.row_count(), 1);
+        assert_eq!(second_sink.row_count(), 1);
+
+        first.write(&TestRow { id: 3 });
+        let mut next_cut = std::pin::pin!(first.commit_on_notify());
+        assert!(next_cut.as_mut().poll(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn test_final_empty_flush() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::<TestRow>::new();
+            let inserter = Inserter::new(sink.insert_fn()).with_final_empty_flush(true);
+            inserter.end().await.unwrap();
+            assert_eq!(sink.batches(), vec![Vec::new()]);
+
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_final_empty_flush(true);
+            inserter.write(&TestRow { id: 1 });
+            inserter.end().await.unwrap();
+            assert_eq!(sink.batches(), vec![vec![TestRow { id: 1 }]]);
+        });
+    }
+
+    #[test]
+    fn test_flush_complete_transactions() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn());
+
+            for ids in [[1, 2], [3, 4]] {
+                let mut tx = inserter.transaction();
+                for id in ids {
+                    tx.write(&TestRow { id });
+                }
+            }
+            inserter.write(&TestRow { id: 5 });
+
+            let flushed = inserter.flush_complete_transactions().await.unwrap();
+            assert_eq!(
+                flushed,
+                Quantities {
+                    rows: 4,
+                    transactions: 2,
+                    bytes: 0,
+                }
+            );
+            assert_eq!(sink.row_count(), 4);
+            assert_eq!(
+                inserter.pending(),
+                &Quantities {
+                    rows: 1,
+                    transactions: 1,
+                    bytes: 0,
+                }
+            );
+
+            inserter.write(&TestRow { id: 6 });
+            inserter.end_transaction();
+            inserter.flush_complete_transactions().await.unwrap();
+            assert_eq!(sink.row_count(), 6);
+        });
+    }
+
+    #[test]
+    fn test_set_and_clear_commit_callback() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn());
+            let calls = Arc::new(Mutex::new(0));
+            let calls_clone = Arc::clone(&calls);
+
+            inserter.set_commit_callback(move |_| {
+                *calls_clone.lock().unwrap() += 1;
+            });
+            inserter.write(&TestRow { id: 1 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(*calls.lock().unwrap(), 1);
+
+            inserter.clear_commit_callback();
+            inserter.write(&TestRow { id: 2 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(*calls.lock().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_min_flush_interval() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_max_rows(2)
+                .with_min_flush_interval(Duration::from_secs(1))
+                .with_clock(clock.clone());
+
+            for id in 0..4 {
+                inserter.write(&TestRow { id });
+                inserter.commit().await.unwrap();
+            }
+            assert_eq!(sink.batch_count(), 1);
+            assert!(!inserter.is_due());
+
+            clock.advance(Duration::from_millis(999));
+            inserter.commit().await.unwrap();
+            assert_eq!(sink.batch_count(), 1);
+
+            clock.advance(Duration::from_millis(1));
+            inserter.commit().await.unwrap();
+            assert_eq!(sink.batches()[1].len(), 2);
+
+            inserter.write(&TestRow { id: 4 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(sink.batch_count(), 3);
+        });
+    }
+
+    #[test]
+    fn test_with_limits() {
+        let sink = crate::test_util::RecordingSink::<TestRow>::new();
+        let inserter = Inserter::new(sink.insert_fn()).with_limits(Limits {
+            max_rows: 500,
+            max_bytes: 1 << 20,
+            max_transactions: 50,
+            period: Some(Duration::from_secs(3)),
+            idle_timeout: Some(Duration::from_secs(1)),
+        });
+        assert_eq!(inserter.max_rows(), 500);
+        assert_eq!(inserter.max_transactions(), 50);
+        assert_eq!(inserter.period(), Some(Duration::from_secs(3)));
+        assert_eq!(inserter.idle_timeout(), Some(Duration::from_secs(1)));
+
+        let inserter = Inserter::new(sink.insert_fn()).with_limits(Limits::default());
+        assert_eq!(inserter.max_rows(), u64::MAX);
+        assert!(!inserter.has_period());
+        assert_eq!(inserter.idle_timeout(), None);
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_idle_timeout(Duration::from_secs(2))
+                .with_clock(clock.clone());
+
+            assert_eq!(inserter.commit().await.unwrap().rows, 0);
+            inserter.write(&TestRow { id: 1 });
+            clock.advance(Duration::from_secs(1));
+            inserter.write(&TestRow { id: 2 });
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(inserter.commit().await.unwrap().rows, 0);
+
+            clock.advance(Duration::from_secs(1));
+            assert_eq!(inserter.commit().await.unwrap().rows, 2);
+            assert_eq!(sink.batches().len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_null_inserter() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null().with_max_rows(2);
+            for id in 0..5 {
+                inserter.write(&TestRow { id });
+                inserter.commit().await.unwrap();
+            }
+            let committed = inserter.end().await.unwrap();
+            assert_eq!(committed.rows, 5);
+        });
+    }
+
+    fn replica(fails: bool) -> impl FnMut(Vec<TestRow>) -> ReadyInsert {
+        move |_batch| {
+            std::future::ready(if fails {
+                Err(io::Error::other("replica down"))
+            } else {
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn test_quorum_sinks() {
+        pollster::block_on(async {
+            let sinks = vec![replica(false), replica(true), replica(false)];
+            let mut inserter = Inserter::new(crate::quorum_sinks(sinks, 2));
+            inserter.write(&TestRow { id: 1 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 1);
+
+            let sinks = vec![replica(true), replica(false), replica(true)];
+            let mut inserter = Inserter::new(crate::quorum_sinks(sinks, 2));
+            inserter.write(&TestRow { id: 1 });
+            let err = inserter.force_commit().await.unwrap_err();
+            assert_eq!(err.into_inner().to_string(), "replica down");
+        });
+    }
+
+    #[test]
+    fn test_tee() {
+        struct Errors(Arc<Mutex<Vec<String>>>);
+
+        impl crate::Observer for Errors {
+            fn on_tee_error(&mut self, error: &(dyn Error + Send + Sync + 'static)) {
+                self.0.lock().unwrap().push(error.to_string());
+            }
+        }
+
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let audited = Arc::new(Mutex::new(Vec::new()));
+            let audited_clone = Arc::clone(&audited);
+            let tee_errors = Arc::new(Mutex::new(Vec::new()));
+
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_tee(move |batch: &[TestRow]| {
+                    audited_clone.lock().unwrap().push(batch.len());
+                    let result = if batch.len() > 1 {
+                        Err(io::Error::other("audit down"))
+                    } else {
+                        Ok(())
+                    };
+                    async move { result }
+                })
+                .with_observer(Errors(Arc::clone(&tee_errors)));
+
+            inserter.write(&TestRow { id: 1 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 1);
+            inserter.write(&TestRow { id: 2 });
+            inserter.write(&TestRow { id: 3 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 2);
+
+            assert_eq!(*audited.lock().unwrap(), vec![1, 2]);
+            assert_eq!(*tee_errors.lock().unwrap(), vec!["audit down"]);
+            assert_eq!(sink.row_count(), 3);
+        });
+    }
+
+    #[test]
+    fn test_transaction_tracking_disabled() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null().with_transaction_tracking(false);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write_in_transaction(7, TestRow { id: 2 });
+            inserter.transaction().write(&TestRow { id: 3 });
+            assert_eq!(inserter.pending().rows, 3);
+            assert_eq!(inserter.pending().transactions, 0);
+
+            let flushed = inserter.force_commit().await.unwrap();
+            assert_eq!(flushed.rows, 3);
+            assert_eq!(flushed.transactions, 0);
+            assert_eq!(inserter.finish().await.unwrap().committed.transactions, 0);
+        });
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        pollster::block_on(async {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let commits_clone = Arc::clone(&events);
+            let checkpoints_clone = Arc::clone(&events);
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_batch_finalizer(|batch| batch.push(TestRow { id: 0 }))
+                .with_commit_callback(move |_| commits_clone.lock().unwrap().push(None))
+                .with_checkpoint_callback(move |position| {
+                    checkpoints_clone.lock().unwrap().push(Some(position));
+                });
+            assert_eq!(inserter.checkpoint(), 0);
+
+            inserter.write_many((1..=3).map(|id| TestRow { id }));
+            inserter.force_commit().await.unwrap();
+            assert_eq!(inserter.checkpoint(), 3);
+
+            inserter.write(&TestRow { id: 4 });
+            inserter.force_commit().await.unwrap();
+            inserter.force_commit().await.unwrap();
+            assert_eq!(inserter.checkpoint(), 4);
+            assert_eq!(*events.lock().unwrap(), vec![None, Some(3), None, Some(4)]);
+        });
+    }
+
+    #[test]
+    fn test_write_many() {
+        let mut inserter = Inserter::<TestRow>::null()
+            .with_buffer_limit(3)
+            .with_overflow_policy(OverflowPolicy::DropNewest);
+
+        inserter.write_many((0..2).map(|id| TestRow { id }));
+        inserter.write_many_ref(&[TestRow { id: 2 }, TestRow { id: 3 }]);
+
+        assert_eq!(inserter.buffer_len(), 3);
+        assert_eq!(inserter.dropped(), 1);
+        assert_eq!(inserter.pending().rows, 3);
+        assert_eq!(inserter.pending().transactions, 1);
+    }
+
+    #[test]
+    fn test_write_all() {
+        let rows = [TestRow { id: 1 }, TestRow { id: 2 }];
+        let vec = vec![TestRow { id: 1 }, TestRow { id: 2 }];
+        let mut from_vec = Inserter::<TestRow>::null();
+        let mut from_array = Inserter::<TestRow>::null();
+        let mut from_slice = Inserter::<TestRow>::null();
+
+        from_vec.write_all(vec);
+        from_array.write_all(rows.clone());
+        from_slice.write_all(&rows[..]);
+
+        for inserter in [&from_vec, &from_array, &from_slice] {
+            assert_eq!(inserter.buffer, rows);
+            assert_eq!(inserter.pending().rows, 2);
+            assert_eq!(inserter.pending().transactions, 1);
+        }
+
+        from_slice.write_all(&rows[..1]);
+        assert_eq!(from_slice.pending().transactions, 2);
+    }
+
+    #[test]
+    fn test_async_commit_callback() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let offsets = Arc::new(Mutex::new(Vec::new()));
+        let offsets_clone = Arc::clone(&offsets);
+        let sync_calls = Arc::new(Mutex::new(0));
+        let sync_calls_clone = Arc::clone(&sync_calls);
+
+        let mut inserter = Inserter::<TestRow>::null()
+            .with_commit_callback(move |_| *sync_calls_clone.lock().unwrap() += 1)
+            .with_async_commit_callback(move |flushed| {
+                let offsets = Arc::clone(&offsets_clone);
+                let rows = flushed.rows;
                 async move {
-                    inserted.borrow_mut().push(batch);
-                    Ok::<_, io::Error>(())
+                    YieldOnce { yielded: false }.await.unwrap();
+                    offsets.lock().unwrap().push(rows);
+                }
+            });
+
+        inserter.write(&TestRow { id: 1 });
+        assert!(inserter.poll_force_commit(&mut cx).is_pending());
+        assert_eq!(inserter.committed().rows, 1);
+        assert_eq!(*sync_calls.lock().unwrap(), 1);
+        assert!(offsets.lock().unwrap().is_empty());
+
+        inserter.write(&TestRow { id: 2 });
+        assert!(matches!(
+            inserter.poll_commit(&mut cx),
+            Poll::Ready(Ok(Quantities { rows: 1, .. }))
+        ));
+        assert_eq!(*offsets.lock().unwrap(), vec![1]);
+        assert_eq!(inserter.pending().rows, 1);
+    }
+
+    #[test]
+    fn test_pre_commit_callback() {
+        pollster::block_on(async {
+            let open = Arc::new(Mutex::new(false));
+            let open_clone = Arc::clone(&open);
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let seen_clone = Arc::clone(&seen);
+            let sink = crate::test_util::RecordingSink::new();
+
+            let mut inserter = Inserter::new(sink.insert_fn()).with_pre_commit_callback(
+                move |batch: &[TestRow], flushed| {
+                    seen_clone.lock().unwrap().push((batch.len(), flushed.rows));
+                    *open_clone.lock().unwrap()
+                },
+            );
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            let pending = *inserter.pending();
+            assert!(inserter.force_commit().await.unwrap().is_empty());
+            assert_eq!(*inserter.pending(), pending);
+            assert_eq!(inserter.buffer_len(), 2);
+            assert_eq!(sink.batch_count(), 0);
+
+            *open.lock().unwrap() = true;
+            inserter.write(&TestRow { id: 3 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 3);
+            assert_eq!(
+                sink.batches(),
+                vec![vec![
+                    TestRow { id: 1 },
+                    TestRow { id: 2 },
+                    TestRow { id: 3 }
+                ]]
+            );
+            assert_eq!(*seen.lock().unwrap(), vec![(2, 2), (3, 3)]);
+        });
+    }
+
+    #[test]
+    fn test_limit_fn() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null().with_limit_fn(|_pending, rows| {
+                rows.iter()
+                    .map(|row| row.id % 10)
+                    .collect::<HashSet<_>>()
+                    .len()
+                    > 2
+            });
+
+            for id in [1, 11, 2, 12] {
+                inserter.write(&TestRow { id });
+                assert!(inserter.commit().await.unwrap().is_empty());
+            }
+            inserter.write(&TestRow { id: 3 });
+            assert_eq!(inserter.commit().await.unwrap().rows, 5);
+        });
+    }
+
+    #[test]
+    fn test_limit_fn_keeps_state() {
+        pollster::block_on(async {
+            let mut checks = 0;
+            let mut inserter = Inserter::<TestRow>::null().with_limit_fn(move |_pending, _rows| {
+                checks += 1;
+                checks % 3 == 0
+            });
+
+            for id in 0..2 {
+                inserter.write(&TestRow { id });
+                assert!(inserter.commit().await.unwrap().is_empty());
+            }
+            inserter.write(&TestRow { id: 2 });
+            assert_eq!(inserter.commit().await.unwrap().rows, 3);
+        });
+    }
+
+    #[test]
+    fn test_memory_pressure_flush() {
+        pollster::block_on(async {
+            let tight = Arc::new(AtomicBool::new(false));
+            let tight_clone = Arc::clone(&tight);
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_memory_pressure_flush(move || tight_clone.load(Ordering::Relaxed));
+
+            inserter.write(&TestRow { id: 1 });
+            assert!(inserter.commit().await.unwrap().is_empty());
+
+            tight.store(true, Ordering::Relaxed);
+            assert!(inserter.is_due());
+            assert_eq!(inserter.commit().await.unwrap().rows, 1);
+            assert!(!inserter.is_due());
+        });
+    }
+
+    #[test]
+    fn test_end_or_recover() {
+        pollster::block_on(async {
+            let down = Arc::new(Mutex::new(true));
+            let down_clone = Arc::clone(&down);
+            let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+                let result = if *down_clone.lock().unwrap() {
+                    Err(io::Error::other("down"))
+                } else {
+                    Ok(())
+                };
+                async move { result }
+            })
+            .with_restore_on_failure();
+            inserter.write(&TestRow { id: 1 });
+
+            let (inserter, err) = inserter.end_or_recover().await.unwrap_err();
+            assert_eq!(err.into_inner().to_string(), "down");
+            assert_eq!(inserter.buffer_len(), 1);
+
+            *down.lock().unwrap() = false;
+            assert_eq!(inserter.end_or_recover().await.ok().unwrap().rows, 1);
+        });
+    }
+
+    #[test]
+    fn test_reset_committed() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null();
+            inserter.write(&TestRow { id: 1 });
+            inserter.force_commit().await.unwrap();
+            inserter.write(&TestRow { id: 2 });
+
+            assert_eq!(inserter.committed().rows, 1);
+            assert_eq!(inserter.reset_committed().rows, 1);
+            assert!(inserter.committed().is_empty());
+            assert_eq!(inserter.pending().rows, 1);
+            assert_eq!(inserter.buffer_len(), 1);
+
+            assert_eq!(inserter.end().await.unwrap().rows, 1);
+        });
+    }
+
+    #[test]
+    fn test_pending_items_mut() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn());
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+
+            for row in inserter.pending_items_mut() {
+                row.id += 100;
+            }
+            assert_eq!(inserter.pending().rows, 2);
+            inserter.force_commit().await.unwrap();
+            assert_eq!(
+                sink.batches(),
+                vec![vec![TestRow { id: 101 }, TestRow { id: 102 }]]
+            );
+        });
+    }
+
+    #[test]
+    fn test_buffer_len() {
+        let mut inserter = Inserter::<TestRow>::null();
+        assert!(inserter.is_buffer_empty());
+
+        inserter.write(&TestRow { id: 1 });
+        inserter.write_priority(TestRow { id: 2 });
+        assert_eq!(inserter.buffer_len(), 2);
+        assert!(!inserter.is_buffer_empty());
+
+        pollster::block_on(inserter.force_commit()).unwrap();
+        assert_eq!(inserter.buffer_len(), 0);
+        assert!(inserter.is_buffer_empty());
+    }
+
+    #[test]
+    fn test_buffer_memory_estimate() {
+        let mut inserter = Inserter::<TestRow>::null();
+        assert_eq!(inserter.buffer_memory_estimate(), 0);
+
+        for id in 0..10 {
+            inserter.write(&TestRow { id });
+        }
+        assert_eq!(
+            inserter.buffer_memory_estimate(),
+            inserter.buffer.capacity() * size_of::<TestRow>()
+        );
+        assert!(inserter.buffer_memory_estimate() >= 10 * size_of::<TestRow>());
+
+        let mut inserter = Inserter::<TestRow>::null().with_size_fn(|_row| 100);
+        for id in 0..10 {
+            inserter.write(&TestRow { id });
+        }
+        assert_eq!(
+            inserter.buffer_memory_estimate(),
+            inserter.buffer.capacity() * size_of::<TestRow>() + 1000
+        );
+    }
+
+    #[test]
+    fn test_commit_every() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_commit_every(3);
+
+            for id in 0..8 {
+                inserter.write(&TestRow { id });
+                assert_eq!(inserter.is_due(), (id + 1) % 3 == 0);
+                inserter.commit().await.unwrap();
+            }
+
+            assert_eq!(sink.batch_count(), 2);
+            assert_eq!(inserter.pending().rows, 2);
+        });
+    }
+
+    #[cfg(feature = "sink")]
+    #[test]
+    fn test_into_sink() {
+        use futures::StreamExt;
+
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let rows = futures::stream::iter((0..7).map(|id| Ok(TestRow { id })));
+            rows.forward(Inserter::new(sink.insert_fn()).with_max_rows(3).into_sink())
+                .await
+                .unwrap();
+            let sizes: Vec<usize> = sink.batches().iter().map(Vec::len).collect();
+            assert_eq!(sizes, vec![3, 3, 1]);
+
+            let empty = crate::test_util::RecordingSink::<TestRow>::new();
+            futures::stream::empty()
+                .forward(
+                    Inserter::new(empty.insert_fn())
+                        .with_final_empty_flush(true)
+                        .into_sink(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(empty.batches(), vec![Vec::<TestRow>::new()]);
+        });
+    }
+
+    #[cfg(all(feature = "sink", feature = "tokio"))]
+    #[test]
+    fn test_into_sink_detached() {
+        use futures::Sink;
+
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let sink = crate::test_util::RecordingSink::new();
+        let (inserter, mut flusher) = Inserter::new_detached(4, sink.insert_fn());
+        let mut rows = inserter.into_sink();
+        for id in 0..3 {
+            Pin::new(&mut rows).start_send(TestRow { id }).unwrap();
+        }
+
+        assert!(Pin::new(&mut rows).poll_close(&mut cx).is_pending());
+        assert!(Pin::new(&mut flusher).poll(&mut cx).is_pending());
+        assert_eq!(sink.row_count(), 3);
+        let Poll::Ready(result) = Pin::new(&mut rows).poll_close(&mut cx) else {
+            panic!("closing should complete once the queue is drained");
+        };
+        result.unwrap();
+
+        let (inserter, mut flusher) = Inserter::new_detached(4, |_batch: Vec<TestRow>| {
+            std::future::ready(Err::<(), _>(io::Error::other("down")))
+        });
+        let mut rows = inserter.into_sink();
+        Pin::new(&mut rows).start_send(TestRow { id: 1 }).unwrap();
+        assert!(Pin::new(&mut rows).poll_close(&mut cx).is_pending());
+        assert!(Pin::new(&mut flusher).poll(&mut cx).is_ready());
+        let Poll::Ready(result) = Pin::new(&mut rows).poll_close(&mut cx) else {
+            panic!("closing should fail once the flusher stopped");
+        };
+        assert_eq!(result.unwrap_err().into_inner().lost_rows(), 1);
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_batching_stream() {
+        use futures::StreamExt;
+
+        pollster::block_on(async {
+            let (inserter, stream) = Inserter::batching_stream();
+            let mut inserter = inserter.with_max_rows(100);
+            for id in 0..250 {
+                inserter.write(&TestRow { id });
+                inserter.commit().await.unwrap();
+            }
+            inserter.end().await.unwrap();
+
+            let sizes: Vec<usize> = stream.map(|batch| batch.len()).collect().await;
+            assert_eq!(sizes, vec![100, 100, 50]);
+        });
+    }
+
+    #[test]
+    fn test_batch_finalizer() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter =
+                Inserter::new(sink.insert_fn()).with_batch_finalizer(|batch: &mut Vec<TestRow>| {
+                    batch.push(TestRow { id: u64::MAX });
+                });
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            let flushed = inserter.force_commit().await.unwrap();
+
+            assert_eq!(flushed.rows, 3);
+            assert_eq!(
+                sink.batches(),
+                vec![vec![
+                    TestRow { id: 1 },
+                    TestRow { id: 2 },
+                    TestRow { id: u64::MAX },
+                ]]
+            );
+        });
+    }
+
+    #[test]
+    fn test_commit_budget() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_max_rows(100);
+            for id in 0..500 {
+                inserter.write(&TestRow { id });
+            }
+
+            let flushed = inserter.commit_budget(2).await.unwrap();
+            assert_eq!(flushed.rows, 200);
+            assert_eq!(sink.batch_count(), 2);
+            assert_eq!(inserter.pending().rows, 300);
+
+            inserter.commit_budget(2).await.unwrap();
+            inserter.commit_budget(2).await.unwrap();
+            assert_eq!(sink.batch_count(), 5);
+            assert_eq!(inserter.pending().rows, 0);
+            assert!(sink.batches().iter().all(|batch| batch.len() == 100));
+        });
+    }
+
+    #[test]
+    fn test_auto_capacity() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_max_rows(64)
+                .with_auto_capacity(true);
+            assert_eq!(inserter.buffer.capacity(), 64);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(inserter.buffer.capacity(), 64);
+
+            let inserter = Inserter::<TestRow>::null()
+                .with_max_rows(u64::MAX - 1)
+                .with_auto_capacity(true);
+            assert_eq!(inserter.buffer.capacity(), MAX_AUTO_CAPACITY);
+        });
+    }
+
+    #[test]
+    fn test_buffered() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_limit_fn(|_, buffered| buffered.iter().map(|row| row.id).sum::<u64>() >= 5);
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            assert_eq!(inserter.buffered(), [TestRow { id: 1 }, TestRow { id: 2 }]);
+            assert!(inserter.commit().await.unwrap().is_empty());
+
+            inserter.write(&TestRow { id: 3 });
+            assert_eq!(inserter.commit().await.unwrap().rows, 3);
+            assert!(inserter.buffered().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_discard() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_max_rows(3)
+                .with_size_fn(|_: &TestRow| 10);
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+
+            let discarded = inserter.discard();
+            assert_eq!(discarded.rows, 2);
+            assert_eq!(discarded.transactions, 1);
+            assert_eq!(discarded.bytes, 20);
+            assert!(inserter.is_buffer_empty());
+            assert!(inserter.pending().is_empty());
+            assert_eq!(inserter.dropped(), 0);
+
+            inserter.write(&TestRow { id: 3 });
+            inserter.force_commit().await.unwrap();
+            assert_eq!(sink.batches(), vec![vec![TestRow { id: 3 }]]);
+        });
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null()
+                .with_max_rows(2)
+                .with_capacity(100);
+            assert!(inserter.buffer.capacity() >= 100);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            assert_eq!(inserter.commit().await.unwrap().rows, 2);
+            assert!(inserter.buffer.capacity() >= 100);
+        });
+    }
+
+    #[test]
+    fn test_clear_dedup() {
+        let mut inserter = Inserter::<TestRow>::null().with_cross_batch_dedup(
+            |row: &TestRow| row.id,
+            100,
+            Duration::from_secs(60),
+        );
+        assert_eq!(inserter.dedup_len(), 0);
+
+        for id in [1, 2, 3, 1] {
+            inserter.write(&TestRow { id });
+        }
+        assert_eq!(inserter.dedup_len(), 3);
+        assert_eq!(inserter.pending().rows, 3);
+
+        inserter.clear_dedup();
+        assert_eq!(inserter.dedup_len(), 0);
+        inserter.write(&TestRow { id: 1 });
+        assert_eq!(inserter.pending().rows, 4);
+    }
+
+    #[test]
+    fn test_advance_trips_period() {
+        let mut inserter = Inserter::<TestRow>::null().with_period(Duration::from_secs(30));
+        inserter.write(&TestRow { id: 1 });
+        assert!(!inserter.is_due());
+
+        inserter.advance(Duration::from_secs(29));
+        assert!(!inserter.is_due());
+        inserter.advance(Duration::from_secs(1));
+        assert!(inserter.is_due());
+    }
+
+    #[test]
+    fn test_batch_meta() {
+        pollster::block_on(async {
+            let seen: Rc<RefCell<Vec<(u32, usize)>>> = Rc::new(RefCell::new(Vec::new()));
+            let seen_clone = Rc::clone(&seen);
+            let mut inserter = Inserter::new_with_meta(
+                move |batch: Vec<TestRow>, version: u32| {
+                    seen_clone.borrow_mut().push((version, batch.len()));
+                    std::future::ready(Ok::<_, io::Error>(()))
+                },
+                0,
+            )
+            .with_batch_meta(1);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.force_commit().await.unwrap();
+            inserter.set_batch_meta(2);
+            inserter.write(&TestRow { id: 2 });
+            inserter.write(&TestRow { id: 3 });
+            inserter.force_commit().await.unwrap();
+            inserter.end().await.unwrap();
+
+            assert_eq!(*seen.borrow(), vec![(1, 1), (2, 2)]);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "inserter dropped with 1 unflushed rows")]
+    fn test_drop_check() {
+        let mut inserter =
+            Inserter::new(|_batch: Vec<TestRow>| std::future::ready(Ok::<_, io::Error>(())))
+                .with_drop_check(true);
+        inserter.write(&TestRow { id: 1 });
+    }
+
+    #[test]
+    fn test_drop_check_after_end() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_drop_check(true);
+            inserter.write(&TestRow { id: 1 });
+            assert_eq!(inserter.end().await.unwrap().rows, 1);
+
+            let mut inserter = Inserter::new(|_batch: Vec<TestRow>| {
+                std::future::ready(Err::<(), _>(io::Error::other("down")))
+            })
+            .with_restore_on_failure()
+            .with_drop_check(true);
+            inserter.write(&TestRow { id: 2 });
+            assert!(inserter.end().await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_finish_report() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_max_rows(2)
+                .with_buffer_limit(2)
+                .with_overflow_policy(OverflowPolicy::DropNewest)
+                .with_clock(clock.clone());
+
+            clock.advance(Duration::from_secs(1));
+            for id in 0..3 {
+                inserter.write(&TestRow { id });
+            }
+            clock.advance(Duration::from_secs(5));
+            inserter.commit().await.unwrap();
+            inserter.write(&TestRow { id: 3 });
+
+            let report = inserter.finish().await.unwrap();
+            assert_eq!(
+                report,
+                ShutdownReport {
+                    committed: Quantities {
+                        rows: 3,
+                        transactions: 2,
+                        bytes: 0,
+                    },
+                    flushes: 2,
+                    dropped: 1,
+                    retries: 0,
+                    max_flush_latency: Duration::ZERO,
+                    uptime: Duration::from_secs(6),
                 }
+            );
+        });
+    }
+
+    #[test]
+    fn test_finish_report_retries_and_final_empty_flush() {
+        pollster::block_on(async {
+            let calls = Arc::new(AtomicU64::new(0));
+            let calls_clone = Arc::clone(&calls);
+            let sizes = Arc::new(Mutex::new(Vec::new()));
+            let sizes_clone = Arc::clone(&sizes);
+            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
+                sizes_clone.lock().unwrap().push(batch.len());
+                let result = if calls_clone.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(io::Error::other("down"))
+                } else {
+                    Ok(())
+                };
+                std::future::ready(result)
             })
-            .with_max_rows(100);
+            .with_retries(3)
+            .with_final_empty_flush(true);
 
             inserter.write(&TestRow { id: 1 });
+            inserter.force_commit().await.unwrap();
 
-            let stats = inserter.force_commit().await.unwrap();
-            assert_eq!(stats.rows, 1);
+            let report = inserter.finish().await.unwrap();
+            assert_eq!(report.retries, 1);
+            assert_eq!(report.flushes, 1);
+            assert_eq!(*sizes.lock().unwrap(), vec![1, 1, 0]);
         });
     }
 
     #[test]
-    fn test_end() {
+    fn test_pressure_threshold() {
         pollster::block_on(async {
-            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
-            let inserted_clone = Rc::clone(&inserted);
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_max_rows(10)
+                .with_size_fn(|_row| 10)
+                .with_max_bytes(100)
+                .with_period(Duration::from_secs(10))
+                .with_pressure_threshold(0.7)
+                .with_clock(clock.clone());
 
-            let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
-                let inserted = Rc::clone(&inserted_clone);
+            for id in 0..6 {
+                inserter.write(&TestRow { id });
+            }
+            clock.advance(Duration::from_secs(5));
+            assert!((inserter.pressure() - 0.58).abs() < 1e-9);
+            inserter.commit().await.unwrap();
+            assert_eq!(sink.batch_count(), 0);
+
+            clock.advance(Duration::from_secs(3));
+            assert!((inserter.pressure() - 0.64).abs() < 1e-9);
+            assert!(!inserter.is_due());
+
+            // 0.5 * 0.7 + 0.3 * 0.7 + 0.2 * 0.8, with every limit still short.
+            inserter.write(&TestRow { id: 6 });
+            assert!((inserter.pressure() - 0.72).abs() < 1e-9);
+            assert!(inserter.is_due());
+            inserter.commit().await.unwrap();
+            assert_eq!(sink.batches()[0].len(), 7);
+        });
+    }
+
+    #[test]
+    fn test_write_in_transaction() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::<TestRow>::null();
+            for (txn_id, id) in [(7, 1), (9, 2), (7, 3), (9, 4), (7, 5)] {
+                inserter.write_in_transaction(txn_id, TestRow { id });
+            }
+            assert_eq!(
+                inserter.pending(),
+                &Quantities {
+                    rows: 5,
+                    transactions: 2,
+                    bytes: 0,
+                }
+            );
+
+            inserter.force_commit().await.unwrap();
+            inserter.write_in_transaction(7, TestRow { id: 6 });
+            assert_eq!(inserter.pending().transactions, 1);
+        });
+    }
+
+    #[test]
+    fn test_flush_if_idle() {
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_clock(clock.clone());
+            let idle = Duration::from_secs(10);
+            assert!(inserter.flush_if_idle(idle).is_none());
+
+            inserter.write(&TestRow { id: 1 });
+            clock.advance(Duration::from_secs(9));
+            assert!(inserter.flush_if_idle(idle).is_none());
+
+            clock.advance(Duration::from_secs(1));
+            inserter.flush_if_idle(idle).unwrap().await.unwrap();
+            assert_eq!(sink.batch_count(), 1);
+            assert!(inserter.flush_if_idle(idle).is_none());
+        });
+    }
+
+    #[test]
+    fn test_estimated_time_to_row_limit() {
+        let clock = crate::test_util::MockClock::new();
+        let mut inserter = Inserter::<TestRow>::null()
+            .with_max_rows(100)
+            .with_clock(clock.clone());
+        assert_eq!(inserter.estimated_time_to_row_limit(), None);
+
+        for id in 0..10 {
+            inserter.write(&TestRow { id });
+            clock.advance(Duration::from_secs(1));
+        }
+
+        assert_eq!(inserter.rows_until_flush(), Some(90));
+        assert!((inserter.ingest_rate().unwrap() - 1.0).abs() < 1e-9);
+        let estimate = inserter.estimated_time_to_row_limit().unwrap();
+        assert!(estimate.abs_diff(Duration::from_secs(90)) < Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_ingest_rate_ewma() {
+        let clock = crate::test_util::MockClock::new();
+        let mut inserter = Inserter::<TestRow>::null().with_clock(clock.clone());
+        inserter.write(&TestRow { id: 0 });
+        assert_eq!(inserter.ingest_rate(), None);
+
+        clock.advance(Duration::from_secs(1));
+        inserter.write(&TestRow { id: 1 });
+        assert!((inserter.ingest_rate().unwrap() - 1.0).abs() < 1e-9);
+
+        // A write after 200ms samples 5 rows/s, moving the average a fifth
+        // of the way there.
+        clock.advance(Duration::from_millis(200));
+        inserter.write(&TestRow { id: 2 });
+        assert!((inserter.ingest_rate().unwrap() - 1.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_max_bytes(100)
+                .with_size_fn(|row: &TestRow| usize::try_from(row.id).unwrap());
+
+            for id in [30, 40] {
+                inserter.write(&TestRow { id });
+                inserter.commit().await.unwrap();
+            }
+            assert_eq!(inserter.pending().bytes, 70);
+            assert_eq!(sink.batch_count(), 0);
+
+            inserter.write(&TestRow { id: 30 });
+            let flushed = inserter.commit().await.unwrap();
+            assert_eq!(flushed.bytes, 100);
+            assert_eq!(inserter.pending().bytes, 0);
+
+            let mut unsized_inserter = Inserter::<TestRow>::null().with_max_bytes(0);
+            unsized_inserter.write(&TestRow { id: 1 });
+            assert!(!unsized_inserter.is_due());
+        });
+    }
+
+    #[test]
+    fn test_new_counting() {
+        pollster::block_on(async {
+            let mut inserter = Inserter::new_counting(|batch: Vec<TestRow>| {
+                let fresh = batch.iter().filter(|row| row.id % 2 == 0).count() as u64;
+                std::future::ready(Ok::<_, io::Error>(fresh))
+            });
+            for id in 0..5 {
+                inserter.write(&TestRow { id });
+            }
+
+            let flushed = inserter.force_commit().await.unwrap();
+            assert_eq!(flushed.rows, 3);
+            assert_eq!(inserter.submitted_rows(), 5);
+            assert_eq!(inserter.end().await.unwrap().rows, 3);
+        });
+    }
+
+    #[test]
+    fn test_max_transactions() {
+        pollster::block_on(async {
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn()).with_max_transactions(2);
+
+            inserter.write(&TestRow { id: 1 });
+            inserter.write(&TestRow { id: 2 });
+            inserter.end_transaction();
+            assert!(!inserter.is_due());
+
+            inserter.write(&TestRow { id: 3 });
+            assert!(inserter.is_due());
+            let flushed = inserter.commit().await.unwrap();
+            assert_eq!(flushed.transactions, 2);
+            assert_eq!(sink.row_count(), 3);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_detached_flusher() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let sink = crate::test_util::RecordingSink::new();
+        let (mut inserter, mut flusher) = Inserter::new_detached(2, sink.insert_fn());
+
+        for id in 0..2 {
+            inserter.write(&TestRow { id });
+            assert!(inserter.poll_force_commit(&mut cx).is_ready());
+        }
+        inserter.write(&TestRow { id: 2 });
+        assert!(inserter.poll_force_commit(&mut cx).is_pending());
+        assert_eq!(sink.batch_count(), 0);
+
+        assert!(Pin::new(&mut flusher).poll(&mut cx).is_pending());
+        assert_eq!(sink.batch_count(), 2);
+        assert!(inserter.poll_force_commit(&mut cx).is_ready());
+
+        drop(inserter);
+        assert!(Pin::new(&mut flusher).poll(&mut cx).is_ready());
+        assert_eq!(sink.row_count(), 3);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_detached_end_waits_for_flusher() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let sink = crate::test_util::RecordingSink::new();
+        let (mut inserter, mut flusher) = Inserter::new_detached(4, sink.insert_fn());
+        for id in 0..3 {
+            inserter.write(&TestRow { id });
+        }
+
+        let mut end = Box::pin(inserter.end());
+        assert!(end.as_mut().poll(&mut cx).is_pending());
+        assert!(Pin::new(&mut flusher).poll(&mut cx).is_pending());
+        assert_eq!(sink.row_count(), 3);
+        let Poll::Ready(committed) = end.as_mut().poll(&mut cx) else {
+            panic!("end should complete once the queue is drained");
+        };
+        assert_eq!(committed.unwrap().rows, 3);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_detached_flusher_failure_reports_lost_rows() {
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let (mut inserter, mut flusher) = Inserter::new_detached(4, |_batch: Vec<TestRow>| {
+            std::future::ready(Err::<(), _>(io::Error::other("down")))
+        });
+        inserter.write(&TestRow { id: 1 });
+        assert!(inserter.poll_force_commit(&mut cx).is_ready());
+        inserter.write(&TestRow { id: 2 });
+        inserter.write(&TestRow { id: 3 });
+        assert!(inserter.poll_force_commit(&mut cx).is_ready());
+
+        let Poll::Ready(result) = Pin::new(&mut flusher).poll(&mut cx) else {
+            panic!("flusher should stop on the failed insert");
+        };
+        assert_eq!(result.unwrap_err().to_string(), "down");
+
+        let err = pollster::block_on(inserter.end()).unwrap_err();
+        assert_eq!(err.into_inner().lost_rows(), 3);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_insert_timeout() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let hung = Arc::new(Mutex::new(true));
+            let hung_clone = Arc::clone(&hung);
+            let sink = crate::test_util::RecordingSink::new();
+            let mut record = sink.insert_fn();
+            let mut inserter = Inserter::new(move |batch| {
+                let hung = std::mem::take(&mut *hung_clone.lock().unwrap());
+                let result = (!hung).then(|| record(batch));
                 async move {
-                    inserted.borrow_mut().push(batch);
+                    match result {
+                        Some(result) => result.await,
+                        None => std::future::pending().await,
+                    }
+                }
+            })
+            .with_insert_timeout(Duration::from_millis(10));
+
+            inserter.write(&TestRow { id: 1 });
+            let err = inserter.force_commit().await.unwrap_err();
+            assert!(err.is_timeout());
+            assert_eq!(
+                err.to_string(),
+                "inserter error: insert timed out after 10ms"
+            );
+            assert!(err.into_insert_error().is_none());
+            assert_eq!(inserter.buffered(), [TestRow { id: 1 }]);
+            assert_eq!(inserter.pending().rows, 1);
+
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 1);
+            assert_eq!(sink.batches(), vec![vec![TestRow { id: 1 }]]);
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_insert_timeout_retried() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let calls = Arc::new(AtomicU64::new(0));
+            let calls_clone = Arc::clone(&calls);
+            let mut inserter = Inserter::new(move |_batch: Vec<TestRow>| {
+                let first = calls_clone.fetch_add(1, Ordering::SeqCst) == 0;
+                async move {
+                    if first {
+                        std::future::pending::<()>().await;
+                    }
                     Ok::<_, io::Error>(())
                 }
             })
-            .with_max_rows(100);
+            .with_insert_timeout(Duration::from_millis(10))
+            .with_retries(2);
 
             inserter.write(&TestRow { id: 1 });
-            inserter.write(&TestRow { id: 2 });
-            inserter.write(&TestRow { id: 3 });
+            assert_eq!(inserter.force_commit().await.unwrap().rows, 1);
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+        });
+    }
 
-            let stats = inserter.end().await.unwrap();
-            assert_eq!(stats.rows, 3);
-            assert_eq!(stats.transactions, 1);
+    type Batches = Rc<RefCell<Vec<Vec<TestRow>>>>;
+    type ReadyInsert = std::future::Ready<Result<(), io::Error>>;
+    type RecordingInserter =
+        Inserter<TestRow, Box<dyn FnMut(Vec<TestRow>) -> ReadyInsert>, ReadyInsert, io::Error>;
+
+    fn recording_inserter(policy: OverflowPolicy) -> (Batches, RecordingInserter) {
+        let received: Batches = Rc::new(RefCell::new(Vec::new()));
+        let received_clone = Rc::clone(&received);
+
+        let insert_fn: Box<dyn FnMut(Vec<TestRow>) -> ReadyInsert> = Box::new(move |batch| {
+            received_clone.borrow_mut().push(batch);
+            std::future::ready(Ok(()))
         });
+        let inserter = Inserter::new(insert_fn)
+            .with_buffer_limit(2)
+            .with_overflow_policy(policy);
+
+        (received, inserter)
     }
 
     #[test]
-    fn test_commit_callback() {
+    fn test_overflow_grow() {
+        let (_, mut inserter) = recording_inserter(OverflowPolicy::Grow);
+        for id in 0..3 {
+            inserter.write(&TestRow { id });
+        }
+
+        assert_eq!(inserter.pending().rows, 3);
+        assert_eq!(inserter.dropped(), 0);
+    }
+
+    #[test]
+    fn test_overflow_drop_newest() {
         pollster::block_on(async {
-            let callback_called: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
-            let callback_clone = Arc::clone(&callback_called);
+            let (received, mut inserter) = recording_inserter(OverflowPolicy::DropNewest);
+            for id in 0..3 {
+                inserter.write(&TestRow { id });
+            }
 
-            let mut inserter =
-                Inserter::new(|_batch: Vec<TestRow>| async move { Ok::<_, io::Error>(()) })
-                    .with_max_rows(1)
-                    .with_commit_callback(move |_stats| {
-                        *callback_clone.lock().unwrap() = true;
-                    });
+            assert_eq!(inserter.pending().rows, 2);
+            assert_eq!(inserter.dropped(), 1);
 
-            inserter.write(&TestRow { id: 1 });
-            inserter.commit().await.unwrap();
+            inserter.force_commit().await.unwrap();
+            assert_eq!(
+                received.borrow()[0],
+                vec![TestRow { id: 0 }, TestRow { id: 1 }]
+            );
+        });
+    }
 
-            assert!(*callback_called.lock().unwrap());
+    #[test]
+    fn test_overflow_drop_oldest() {
+        pollster::block_on(async {
+            let (received, mut inserter) = recording_inserter(OverflowPolicy::DropOldest);
+            for id in 0..3 {
+                inserter.write(&TestRow { id });
+            }
+
+            assert_eq!(inserter.pending().rows, 2);
+            assert_eq!(inserter.dropped(), 1);
+
+            inserter.force_commit().await.unwrap();
+            assert_eq!(
+                received.borrow()[0],
+                vec![TestRow { id: 1 }, TestRow { id: 2 }]
+            );
         });
     }
 
     #[test]
-    fn test_no_commit_when_below_limit() {
+    fn test_overflow_block() {
         pollster::block_on(async {
-            let inserted: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
-            let inserted_clone = Rc::clone(&inserted);
+            let (received, mut inserter) = recording_inserter(OverflowPolicy::Block);
+            for id in 0..3 {
+                inserter.write_async(TestRow { id }).await.unwrap();
+            }
+
+            assert_eq!(inserter.pending().rows, 1);
+            assert_eq!(inserter.dropped(), 0);
+            assert_eq!(
+                received.borrow()[0],
+                vec![TestRow { id: 0 }, TestRow { id: 1 }]
+            );
+        });
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_run_until_shutdown_flushes_buffer() {
+        pollster::block_on(async {
+            let received: Rc<RefCell<Vec<Vec<TestRow>>>> = Rc::new(RefCell::new(Vec::new()));
+            let received_clone = Rc::clone(&received);
 
             let mut inserter = Inserter::new(move |batch: Vec<TestRow>| {
-                let inserted = Rc::clone(&inserted_clone);
+                let received = Rc::clone(&received_clone);
                 async move {
-                    inserted.borrow_mut().push(batch);
+                    received.borrow_mut().push(batch);
                     Ok::<_, io::Error>(())
                 }
             })
-            .with_max_rows(10);
+            .with_max_rows(100);
 
             inserter.write(&TestRow { id: 1 });
             inserter.write(&TestRow { id: 2 });
 
-            let stats = inserter.commit().await.unwrap();
-            assert_eq!(stats.rows, 0);
+            let writes = futures::stream::pending::<TestRow>();
+            let stats = inserter
+                .run_until_shutdown(writes, std::future::ready(()))
+                .await
+                .unwrap();
 
-            let batches = inserted.borrow();
-            assert!(batches.is_empty());
+            assert_eq!(stats.rows, 2);
+            assert_eq!(received.borrow().len(), 1);
+            assert_eq!(received.borrow()[0].len(), 2);
+        });
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_run_until_shutdown_consumes_stream() {
+        pollster::block_on(async {
+            let inserter =
+                Inserter::new(|_batch: Vec<TestRow>| async move { Ok::<_, io::Error>(()) })
+                    .with_max_rows(2);
+
+            let writes = futures::stream::iter((0..5).map(|id| TestRow { id }));
+            let stats = inserter
+                .run_until_shutdown(writes, std::future::pending::<()>())
+                .await
+                .unwrap();
+
+            assert_eq!(stats.rows, 5);
+            assert_eq!(stats.transactions, 5);
+        });
+    }
+
+    #[cfg(feature = "stream")]
+    #[test]
+    fn test_consume_stream_flushes_while_idle() {
+        use futures::StreamExt;
+
+        pollster::block_on(async {
+            let clock = crate::test_util::MockClock::new();
+            let sleeper = crate::test_util::InstantSleeper::new(clock.clone());
+            let sink = crate::test_util::RecordingSink::new();
+            let mut inserter = Inserter::new(sink.insert_fn())
+                .with_period(Duration::from_secs(5))
+                .with_clock(clock);
+
+            let mut idle = true;
+            let idle_then_end = futures::stream::poll_fn(move |cx| {
+                if std::mem::take(&mut idle) {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Poll::Ready(None)
+            });
+            let rows = futures::stream::once(async { TestRow { id: 1 } }).chain(idle_then_end);
+            let committed = inserter
+                .consume_stream(rows, |left| sleeper.sleep(left))
+                .await
+                .unwrap();
+
+            assert_eq!(committed.rows, 1);
+            assert_eq!(sink.batches(), vec![vec![TestRow { id: 1 }]]);
+            assert!(inserter.is_buffer_empty());
         });
     }
 }
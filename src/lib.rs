@@ -1,8 +1,59 @@
+// The futures and sinks of this crate box every future they poll, so they
+// are `Unpin` whatever their type parameters. Where a parameter such as the
+// row or error type would keep that from being derived, it is implemented by
+// hand next to the type.
+
+#[cfg(feature = "stream")]
+mod batch_stream;
+mod buckets;
+mod clock;
+mod counting;
+mod dedup;
 mod error;
+mod extra;
+#[cfg(feature = "tokio")]
+mod flusher;
 mod inserter;
+mod limits;
+mod map_err;
+mod meta;
+mod observer;
+mod overflow;
 mod quantities;
+mod quorum;
+mod report;
+mod resource;
+mod scheduler;
+#[cfg(feature = "sink")]
+mod sink;
+mod sync;
 mod ticks;
+mod transaction;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 
+#[cfg(feature = "stream")]
+pub use batch_stream::BatchStream;
+pub use clock::{Clock, SystemClock};
+pub use counting::Counting;
 pub use error::InserterError;
+pub use extra::ExtraInserter;
+#[cfg(feature = "tokio")]
+pub use flusher::{Flusher, FlusherClosed, SendBatch};
 pub use inserter::Inserter;
+pub use limits::Limits;
+pub use map_err::MapErr;
+pub use meta::MetaInserter;
+pub use observer::Observer;
+pub use overflow::OverflowPolicy;
 pub use quantities::Quantities;
+pub use quorum::{Quorum, quorum_sinks};
+pub use report::ShutdownReport;
+pub use resource::{WithResource, per_batch_resource};
+pub use scheduler::{PeriodicScheduler, Scheduler};
+#[cfg(feature = "sink")]
+pub use sink::InserterSink;
+pub use sync::SyncInserter;
+pub use ticks::Schedule;
+pub use transaction::Transaction;
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+/// Flush limits applied together with
+/// [`Inserter::with_limits`](crate::Inserter::with_limits).
+///
+/// The default is unlimited, matching a freshly created inserter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_rows: u64,
+    /// Only effective together with a size function, see
+    /// [`Inserter::with_max_bytes`](crate::Inserter::with_max_bytes).
+    pub max_bytes: u64,
+    pub max_transactions: u64,
+    pub period: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_rows: u64::MAX,
+            max_bytes: u64::MAX,
+            max_transactions: u64::MAX,
+            period: None,
+            idle_timeout: None,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Future returned by the insert function of an inserter built with
+/// [`Inserter::map_err`](crate::Inserter::map_err).
+pub struct MapErr<Fut, G> {
+    future: Pin<Box<Fut>>,
+    map: Arc<G>,
+}
+
+impl<Fut, G> MapErr<Fut, G> {
+    pub(crate) fn new(future: Fut, map: Arc<G>) -> Self {
+        Self::from_pinned(Box::pin(future), map)
+    }
+
+    pub(crate) const fn from_pinned(future: Pin<Box<Fut>>, map: Arc<G>) -> Self {
+        Self { future, map }
+    }
+}
+
+impl<Fut, G, E, E2> Future for MapErr<Fut, G>
+where
+    Fut: Future<Output = Result<(), E>>,
+    G: Fn(E) -> E2,
+{
+    type Output = Result<(), E2>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let map = &this.map;
+        this.future
+            .as_mut()
+            .poll(cx)
+            .map(|result| result.map_err(|err| map(err)))
+    }
+}
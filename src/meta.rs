@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::error::InserterError;
+use crate::inserter::Inserter;
+use crate::quantities::Quantities;
+
+/// Inserter returned by [`Inserter::new_with_meta`], which also holds the
+/// batch metadata of type `M` handed to its insert function.
+///
+/// Builders are applied with [`configure`](Self::configure) and everything
+/// else is reachable through `Deref`.
+pub struct MetaInserter<T, F, Fut, E, M>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    inserter: Inserter<T, F, Fut, E>,
+    meta: Arc<Mutex<M>>,
+}
+
+impl<T, F, Fut, E, M> MetaInserter<T, F, Fut, E, M>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    pub(crate) const fn new(inserter: Inserter<T, F, Fut, E>, meta: Arc<Mutex<M>>) -> Self {
+        Self { inserter, meta }
+    }
+
+    /// Applies `builder` to the wrapped inserter.
+    #[must_use]
+    pub fn configure<B>(self, builder: B) -> Self
+    where
+        B: FnOnce(Inserter<T, F, Fut, E>) -> Inserter<T, F, Fut, E>,
+    {
+        Self {
+            inserter: builder(self.inserter),
+            meta: self.meta,
+        }
+    }
+
+    /// Replaces the batch metadata passed to the insert function.
+    #[must_use]
+    pub fn with_batch_meta(mut self, meta: M) -> Self {
+        self.set_batch_meta(meta);
+        self
+    }
+
+    /// Replaces the batch metadata at runtime; the next batch handed to the
+    /// insert function gets the new value.
+    pub fn set_batch_meta(&mut self, meta: M) {
+        *self.meta.lock().unwrap_or_else(PoisonError::into_inner) = meta;
+    }
+
+    /// [`Inserter::end`] of the wrapped inserter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub async fn end(self) -> Result<Quantities, InserterError<E>> {
+        self.inserter.end().await
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Inserter<T, F, Fut, E> {
+        self.inserter
+    }
+}
+
+impl<T, F, Fut, E, M> Deref for MetaInserter<T, F, Fut, E, M>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    type Target = Inserter<T, F, Fut, E>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inserter
+    }
+}
+
+impl<T, F, Fut, E, M> DerefMut for MetaInserter<T, F, Fut, E, M>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inserter
+    }
+}
@@ -0,0 +1,11 @@
+use std::error::Error;
+
+/// Receives events of an inserter that do not fail the flush they happen
+/// in, registered with [`Inserter::with_observer`](crate::Inserter::with_observer).
+pub trait Observer: Send {
+    /// The copy of a batch sent with
+    /// [`Inserter::with_tee`](crate::Inserter::with_tee) failed.
+    fn on_tee_error(&mut self, error: &(dyn Error + Send + Sync + 'static)) {
+        let _ = error;
+    }
+}
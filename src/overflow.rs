@@ -0,0 +1,15 @@
+/// What to do when a write arrives while the buffer is at its limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Keep buffering past the limit.
+    #[default]
+    Grow,
+    /// Discard the incoming item.
+    DropNewest,
+    /// Discard the oldest buffered item to make room.
+    DropOldest,
+    /// Flush the buffer before accepting the item. Only honored by
+    /// [`Inserter::write_async`](crate::Inserter::write_async); synchronous
+    /// writes fall back to [`Grow`](Self::Grow).
+    Block,
+}
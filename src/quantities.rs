@@ -1,17 +1,239 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quantities {
     pub rows: u64,
     pub transactions: u64,
+    /// Estimated size of the rows, from the size function set with
+    /// [`Inserter::with_size_fn`](crate::Inserter::with_size_fn); zero
+    /// without one.
+    pub bytes: u64,
 }
 
 impl Quantities {
     pub const ZERO: Self = Self {
         rows: 0,
         transactions: 0,
+        bytes: 0,
     };
 
     #[must_use]
     pub const fn is_empty(&self) -> bool {
         self.rows == 0
     }
+
+    /// Adds `other` field by field, returning `None` if any field overflows.
+    #[must_use]
+    pub const fn checked_add(self, other: Self) -> Option<Self> {
+        match (
+            self.rows.checked_add(other.rows),
+            self.transactions.checked_add(other.transactions),
+            self.bytes.checked_add(other.bytes),
+        ) {
+            (Some(rows), Some(transactions), Some(bytes)) => Some(Self {
+                rows,
+                transactions,
+                bytes,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Saturating field-wise addition, so long-running totals cap at `u64::MAX`.
+impl Add for Quantities {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            rows: self.rows.saturating_add(other.rows),
+            transactions: self.transactions.saturating_add(other.transactions),
+            bytes: self.bytes.saturating_add(other.bytes),
+        }
+    }
+}
+
+impl AddAssign for Quantities {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for Quantities {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+
+/// Field-wise partial order: one value is greater than another only if no
+/// field is smaller. Values where fields disagree in direction are not
+/// comparable, so this is not a total order.
+impl PartialOrd for Quantities {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let fields = [
+            self.rows.cmp(&other.rows),
+            self.transactions.cmp(&other.transactions),
+            self.bytes.cmp(&other.bytes),
+        ];
+
+        let mut ordering = Ordering::Equal;
+        for field in fields {
+            match (ordering, field) {
+                (_, Ordering::Equal) => {}
+                (Ordering::Equal, field) => ordering = field,
+                (ordering, field) if ordering == field => {}
+                _ => return None,
+            }
+        }
+        Some(ordering)
+    }
+}
+
+/// Formats as `12 rows / 3 transactions`, followed by ` / 512 bytes` when
+/// bytes were estimated.
+impl fmt::Display for Quantities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rows / {} transactions", self.rows, self.transactions)?;
+        if self.bytes > 0 {
+            write!(f, " / {} bytes", self.bytes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_ord() {
+        let threshold = Quantities {
+            rows: 10,
+            transactions: 2,
+            bytes: 0,
+        };
+
+        assert!(
+            Quantities {
+                rows: 10,
+                transactions: 3,
+                bytes: 0,
+            } >= threshold
+        );
+        assert!(Quantities::ZERO < threshold);
+        assert_eq!(threshold.partial_cmp(&threshold), Some(Ordering::Equal));
+
+        let mixed = Quantities {
+            rows: 20,
+            transactions: 1,
+            bytes: 0,
+        };
+        assert_eq!(mixed.partial_cmp(&threshold), None);
+        assert_eq!(
+            Quantities {
+                bytes: 1,
+                ..Quantities::ZERO
+            }
+            .partial_cmp(&Quantities {
+                rows: 1,
+                ..Quantities::ZERO
+            }),
+            None
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let quantities = Quantities {
+            rows: 3,
+            transactions: 2,
+            bytes: 64,
+        };
+        let json = serde_json::to_string(&quantities).unwrap();
+        assert_eq!(json, r#"{"rows":3,"transactions":2,"bytes":64}"#);
+        assert_eq!(
+            serde_json::from_str::<Quantities>(&json).unwrap(),
+            quantities
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let quantities = Quantities {
+            rows: 12345,
+            transactions: 42,
+            bytes: 0,
+        };
+        assert_eq!(quantities.to_string(), "12345 rows / 42 transactions");
+        assert_eq!(
+            Quantities {
+                bytes: 512,
+                ..quantities
+            }
+            .to_string(),
+            "12345 rows / 42 transactions / 512 bytes"
+        );
+    }
+
+    #[test]
+    fn test_add() {
+        let one = Quantities {
+            rows: 1,
+            transactions: 1,
+            bytes: 8,
+        };
+
+        let mut total = Quantities::ZERO;
+        total += one;
+        assert_eq!(total + one, [one, one].into_iter().sum());
+        assert_eq!(
+            total
+                + Quantities {
+                    rows: u64::MAX,
+                    ..Quantities::ZERO
+                },
+            Quantities {
+                rows: u64::MAX,
+                transactions: 1,
+                bytes: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn test_checked_add() {
+        let near_max = Quantities {
+            rows: u64::MAX - 1,
+            transactions: 1,
+            bytes: 0,
+        };
+        let one = Quantities {
+            rows: 1,
+            transactions: 1,
+            bytes: 0,
+        };
+
+        assert_eq!(
+            near_max.checked_add(one),
+            Some(Quantities {
+                rows: u64::MAX,
+                transactions: 2,
+                bytes: 0,
+            })
+        );
+        assert_eq!(near_max.checked_add(one).unwrap().checked_add(one), None);
+        assert_eq!(
+            one.checked_add(Quantities {
+                rows: 0,
+                transactions: u64::MAX,
+                bytes: 0,
+            }),
+            None
+        );
+    }
 }
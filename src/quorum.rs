@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Builds an insert function that sends every batch to all `sinks`
+/// concurrently and succeeds once `required` of them have returned `Ok`.
+///
+/// Sinks still running when the quorum is reached are dropped. The flush
+/// fails with the last sink error as soon as fewer than `required` sinks can
+/// still succeed.
+///
+/// # Panics
+///
+/// Panics if `required` is greater than the number of sinks.
+pub fn quorum_sinks<T, F, Fut, E>(
+    mut sinks: Vec<F>,
+    required: usize,
+) -> impl FnMut(Vec<T>) -> Quorum<Fut, E>
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
+    assert!(
+        required <= sinks.len(),
+        "quorum of {required} exceeds {} sinks",
+        sinks.len()
+    );
+
+    move |batch| Quorum {
+        pending: sinks
+            .iter_mut()
+            .map(|sink| Box::pin(sink(batch.clone())))
+            .collect(),
+        acked: 0,
+        required,
+        last_error: None,
+    }
+}
+
+/// Future returned by the insert function built with [`quorum_sinks`].
+pub struct Quorum<Fut, E> {
+    pending: Vec<Pin<Box<Fut>>>,
+    acked: usize,
+    required: usize,
+    last_error: Option<E>,
+}
+
+impl<Fut, E> Unpin for Quorum<Fut, E> {}
+
+impl<Fut, E> Future for Quorum<Fut, E>
+where
+    Fut: Future<Output = Result<(), E>>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut index = 0;
+        while index < this.pending.len() && this.acked < this.required {
+            match this.pending[index].as_mut().poll(cx) {
+                Poll::Ready(result) => {
+                    drop(this.pending.swap_remove(index));
+                    match result {
+                        Ok(()) => this.acked += 1,
+                        Err(err) => this.last_error = Some(err),
+                    }
+                }
+                Poll::Pending => index += 1,
+            }
+        }
+
+        if this.acked >= this.required {
+            this.pending.clear();
+            return Poll::Ready(Ok(()));
+        }
+        if this.acked + this.pending.len() < this.required {
+            if let Some(err) = this.last_error.take() {
+                this.pending.clear();
+                return Poll::Ready(Err(err));
+            }
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use crate::quantities::Quantities;
+
+/// Lifetime summary returned by [`Inserter::finish`](crate::Inserter::finish).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShutdownReport {
+    /// Rows and transactions committed over the inserter's lifetime.
+    pub committed: Quantities,
+    /// Number of successful flushes.
+    pub flushes: u64,
+    /// Items discarded by the overflow policy, deduplication or sampling.
+    pub dropped: u64,
+    /// Inserts retried after a failure with
+    /// [`with_retries`](crate::Inserter::with_retries).
+    pub retries: u64,
+    /// Longest time a successful flush took, from start to completion.
+    pub max_flush_latency: Duration,
+    /// Time from creating the inserter until the final flush finished.
+    pub uptime: Duration,
+}
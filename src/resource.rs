@@ -0,0 +1,93 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, ready};
+
+/// Builds an insert function that acquires a fresh resource from `provider`
+/// before every insert and hands it to `insert_fn` along with the batch.
+///
+/// This models connection-pool checkout: each call to the returned function
+/// awaits `provider()` first, so a failed checkout fails the flush with the
+/// provider's error. `insert_fn` is only called once the resource is
+/// ready, so with concurrent flushes it is shared behind a lock.
+///
+/// ```
+/// use std::io;
+/// use universal_inserter::{Inserter, per_batch_resource};
+///
+/// struct Connection;
+///
+/// impl Connection {
+///     async fn insert(&self, rows: Vec<u64>) -> io::Result<()> {
+///         Ok(())
+///     }
+/// }
+///
+/// let inserter = Inserter::new(per_batch_resource(
+///     || async { Ok::<_, io::Error>(Connection) },
+///     |conn: Connection, batch: Vec<u64>| async move { conn.insert(batch).await },
+/// ));
+/// # drop(inserter);
+/// ```
+pub fn per_batch_resource<T, R, E, P, RFut, I, IFut>(
+    mut provider: P,
+    insert_fn: I,
+) -> impl FnMut(Vec<T>) -> WithResource<T, RFut, I, IFut>
+where
+    P: FnMut() -> RFut,
+    RFut: Future<Output = Result<R, E>>,
+    I: FnMut(R, Vec<T>) -> IFut,
+    IFut: Future<Output = Result<(), E>>,
+{
+    let insert_fn = Arc::new(Mutex::new(insert_fn));
+    move |batch| WithResource {
+        state: State::Acquiring {
+            resource: Box::pin(provider()),
+            batch: Some(batch),
+        },
+        insert_fn: Arc::clone(&insert_fn),
+    }
+}
+
+/// Future returned by the insert function built with [`per_batch_resource`].
+pub struct WithResource<T, RFut, I, IFut> {
+    state: State<T, RFut, IFut>,
+    insert_fn: Arc<Mutex<I>>,
+}
+
+enum State<T, RFut, IFut> {
+    Acquiring {
+        resource: Pin<Box<RFut>>,
+        batch: Option<Vec<T>>,
+    },
+    Inserting(Pin<Box<IFut>>),
+}
+
+impl<T, RFut, I, IFut> Unpin for WithResource<T, RFut, I, IFut> {}
+
+impl<T, R, E, RFut, I, IFut> Future for WithResource<T, RFut, I, IFut>
+where
+    RFut: Future<Output = Result<R, E>>,
+    I: FnMut(R, Vec<T>) -> IFut,
+    IFut: Future<Output = Result<(), E>>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.state {
+                State::Acquiring { resource, batch } => {
+                    let resource = ready!(resource.as_mut().poll(cx))?;
+                    let batch = batch.take().unwrap_or_default();
+                    let mut insert_fn = this
+                        .insert_fn
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner);
+                    this.state = State::Inserting(Box::pin(insert_fn(resource, batch)));
+                }
+                State::Inserting(insert) => return insert.as_mut().poll(cx),
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+/// Computes when the next time-based flush is due.
+///
+/// Called whenever the timer is (re)scheduled, i.e. on the first write and
+/// after each commit. Returning `None` leaves the time limit unarmed until the
+/// next reschedule.
+pub trait Scheduler: Debug + Send + Sync {
+    fn next_deadline(&self, now: Instant) -> Option<Instant>;
+}
+
+/// Schedules the next deadline one fixed period after `now`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeriodicScheduler {
+    period: Duration,
+}
+
+impl PeriodicScheduler {
+    #[must_use]
+    pub const fn new(period: Duration) -> Self {
+        Self { period }
+    }
+}
+
+impl Scheduler for PeriodicScheduler {
+    fn next_deadline(&self, now: Instant) -> Option<Instant> {
+        now.checked_add(self.period)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periodic_scheduler() {
+        let now = Instant::now();
+        let scheduler = PeriodicScheduler::new(Duration::from_secs(3));
+        assert_eq!(
+            scheduler.next_deadline(now),
+            Some(now + Duration::from_secs(3))
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+
+use futures_sink::Sink;
+
+use crate::error::InserterError;
+use crate::inserter::{Inserter, ShutDown};
+
+/// [`Sink`] returned by [`Inserter::into_sink`].
+///
+/// Sending writes the item. Readiness and flushing commit when a limit is
+/// reached, and closing flushes everything left like
+/// [`end`](Inserter::end).
+pub struct InserterSink<T, F, Fut, E>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    inserter: Inserter<T, F, Fut, E>,
+    closing: ShutDown<Fut>,
+}
+
+impl<T, F, Fut, E> InserterSink<T, F, Fut, E>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    pub(crate) const fn new(inserter: Inserter<T, F, Fut, E>) -> Self {
+        Self {
+            inserter,
+            closing: ShutDown::Flushing,
+        }
+    }
+
+    #[must_use]
+    pub const fn get_ref(&self) -> &Inserter<T, F, Fut, E> {
+        &self.inserter
+    }
+
+    /// Returns the inserter, with anything not yet flushed still buffered.
+    #[must_use]
+    pub fn into_inner(self) -> Inserter<T, F, Fut, E> {
+        self.inserter
+    }
+}
+
+impl<T, F, Fut, E> Unpin for InserterSink<T, F, Fut, E>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+}
+
+impl<T, F, Fut, E> Sink<T> for InserterSink<T, F, Fut, E>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    type Error = InserterError<E>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.get_mut().inserter.write_owned(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.get_mut().inserter.poll_commit(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        this.inserter.poll_shut_down(&mut this.closing, cx)
+    }
+}
@@ -0,0 +1,205 @@
+use std::error::Error;
+use std::future::{Future, Ready, ready};
+use std::ops::{Deref, DerefMut};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use crate::error::InserterError;
+use crate::inserter::Inserter;
+use crate::quantities::Quantities;
+
+/// Inserter for blocking insert functions, e.g. over a synchronous database
+/// connection.
+///
+/// Wraps an [`Inserter`] whose insert futures are always ready, so every
+/// limit, callback and accessor behaves the same. Builders are applied with
+/// [`configure`](Self::configure) and everything else is reachable through
+/// `Deref`; the flushing methods here block instead of returning futures.
+///
+/// ```
+/// use std::convert::Infallible;
+/// use universal_inserter::SyncInserter;
+///
+/// let mut table = Vec::new();
+/// let mut inserter = SyncInserter::new(|mut rows: Vec<u64>| {
+///     table.append(&mut rows);
+///     Ok::<_, Infallible>(())
+/// })
+/// .configure(|inserter| inserter.with_max_rows(100));
+///
+/// inserter.write(&1);
+/// inserter.commit().unwrap();
+/// inserter.end().unwrap();
+/// assert_eq!(table, [1]);
+/// ```
+pub struct SyncInserter<T, F, E>
+where
+    F: FnMut(Vec<T>) -> Ready<Result<(), E>>,
+    E: Error,
+{
+    inserter: Inserter<T, F, Ready<Result<(), E>>, E>,
+}
+
+impl<T, E> SyncInserter<T, fn(Vec<T>) -> Ready<Result<(), E>>, E>
+where
+    E: Error,
+{
+    #[must_use]
+    #[allow(clippy::type_complexity)]
+    pub fn new<G>(
+        mut insert_fn: G,
+    ) -> SyncInserter<T, impl FnMut(Vec<T>) -> Ready<Result<(), E>>, E>
+    where
+        G: FnMut(Vec<T>) -> Result<(), E>,
+    {
+        SyncInserter {
+            inserter: Inserter::new(move |batch| ready(insert_fn(batch))),
+        }
+    }
+}
+
+impl<T, F, E> SyncInserter<T, F, E>
+where
+    F: FnMut(Vec<T>) -> Ready<Result<(), E>>,
+    E: Error,
+{
+    /// Applies `builder` to the wrapped inserter, e.g.
+    /// `.configure(|inserter| inserter.with_max_rows(100))`.
+    #[must_use]
+    pub fn configure<B>(self, builder: B) -> Self
+    where
+        B: FnOnce(
+            Inserter<T, F, Ready<Result<(), E>>, E>,
+        ) -> Inserter<T, F, Ready<Result<(), E>>, E>,
+    {
+        Self {
+            inserter: builder(self.inserter),
+        }
+    }
+
+    /// Blocking form of [`Inserter::commit`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub fn commit(&mut self) -> Result<Quantities, InserterError<E>> {
+        block_on(self.inserter.commit())
+    }
+
+    /// Blocking form of [`Inserter::force_commit`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub fn force_commit(&mut self) -> Result<Quantities, InserterError<E>> {
+        block_on(self.inserter.force_commit())
+    }
+
+    /// Blocking form of [`Inserter::end`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the insert function fails.
+    pub fn end(self) -> Result<Quantities, InserterError<E>> {
+        block_on(self.inserter.end())
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> Inserter<T, F, Ready<Result<(), E>>, E> {
+        self.inserter
+    }
+}
+
+impl<T, F, E> Deref for SyncInserter<T, F, E>
+where
+    F: FnMut(Vec<T>) -> Ready<Result<(), E>>,
+    E: Error,
+{
+    type Target = Inserter<T, F, Ready<Result<(), E>>, E>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inserter
+    }
+}
+
+impl<T, F, E> DerefMut for SyncInserter<T, F, E>
+where
+    F: FnMut(Vec<T>) -> Ready<Result<(), E>>,
+    E: Error,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inserter
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Inserts never pend, but a shared semaphore or an async commit callback
+/// can, so this parks the thread until woken rather than spinning.
+fn block_on<Fut: Future>(future: Fut) -> Fut::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_sync_inserter() {
+        let batches = Rc::new(RefCell::new(Vec::new()));
+        let recorded = Rc::clone(&batches);
+        let clock = crate::test_util::MockClock::new();
+        let mut inserter = SyncInserter::new(move |batch: Vec<u32>| {
+            recorded.borrow_mut().push(batch);
+            Ok::<_, io::Error>(())
+        })
+        .configure(|inserter| {
+            inserter
+                .with_max_rows(3)
+                .with_period(Duration::from_secs(5))
+                .with_clock(clock.clone())
+        });
+
+        for row in 0..4 {
+            inserter.write(&row);
+            inserter.commit().unwrap();
+        }
+        assert_eq!(*batches.borrow(), vec![vec![0, 1, 2]]);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(inserter.commit().unwrap().rows, 1);
+
+        inserter.write(&4);
+        assert_eq!(inserter.end().unwrap().rows, 5);
+        assert_eq!(*batches.borrow(), vec![vec![0, 1, 2], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn test_sync_inserter_error() {
+        let mut inserter = SyncInserter::new(|_batch: Vec<u32>| Err(io::Error::other("disk full")));
+        inserter.write(&1);
+        let err = inserter.force_commit().unwrap_err();
+        assert_eq!(err.into_inner().to_string(), "disk full");
+    }
+}
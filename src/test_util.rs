@@ -0,0 +1,175 @@
+//! Deterministic time and recording sinks for testing code built on [`Inserter`].
+//!
+//! Pair a [`MockClock`] with [`Inserter::with_clock`] to drive time-based
+//! limits in logical time, and a [`RecordingSink`] to capture every batch:
+//!
+//! ```
+//! use std::time::Duration;
+//! use universal_inserter::Inserter;
+//! use universal_inserter::test_util::{MockClock, RecordingSink};
+//!
+//! let clock = MockClock::new();
+//! let sink = RecordingSink::new();
+//! let mut inserter = Inserter::new(sink.insert_fn())
+//!     .with_period(Duration::from_secs(10))
+//!     .with_clock(clock.clone());
+//!
+//! pollster::block_on(async {
+//!     inserter.write(&1);
+//!     assert!(inserter.commit().await.unwrap().is_empty());
+//!
+//!     clock.advance(Duration::from_secs(10));
+//!     assert_eq!(inserter.commit().await.unwrap().rows, 1);
+//! });
+//!
+//! assert_eq!(sink.batches(), vec![vec![1]]);
+//! ```
+//!
+//! [`Inserter`]: crate::Inserter
+//! [`Inserter::with_clock`]: crate::Inserter::with_clock
+
+use std::convert::Infallible;
+use std::future::{Ready, ready};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+
+/// Clock that only moves when [`advance`](Self::advance) is called.
+///
+/// Clones share the same underlying time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Sleeper that completes immediately after advancing a [`MockClock`] by the
+/// requested duration.
+///
+/// ```
+/// use std::time::Duration;
+/// use universal_inserter::Clock;
+/// use universal_inserter::test_util::{InstantSleeper, MockClock};
+///
+/// let clock = MockClock::new();
+/// let sleeper = InstantSleeper::new(clock.clone());
+/// let start = clock.now();
+///
+/// pollster::block_on(sleeper.sleep(Duration::from_secs(60)));
+/// assert_eq!(clock.now() - start, Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone)]
+pub struct InstantSleeper {
+    clock: MockClock,
+}
+
+impl InstantSleeper {
+    #[must_use]
+    pub const fn new(clock: MockClock) -> Self {
+        Self { clock }
+    }
+
+    pub fn sleep(&self, duration: Duration) -> Ready<()> {
+        self.clock.advance(duration);
+        ready(())
+    }
+}
+
+/// Sink that records every batch it is handed.
+///
+/// Clones share the same recording, so keep one handle and pass
+/// [`insert_fn`](Self::insert_fn) to the inserter.
+#[derive(Debug)]
+pub struct RecordingSink<T> {
+    batches: Arc<Mutex<Vec<Vec<T>>>>,
+}
+
+impl<T> Clone for RecordingSink<T> {
+    fn clone(&self) -> Self {
+        Self {
+            batches: Arc::clone(&self.batches),
+        }
+    }
+}
+
+impl<T> Default for RecordingSink<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> RecordingSink<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            batches: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Insert function that records each batch and always succeeds.
+    pub fn insert_fn(&self) -> impl FnMut(Vec<T>) -> Ready<Result<(), Infallible>> + Send + use<T>
+    where
+        T: Send,
+    {
+        let batches = Arc::clone(&self.batches);
+        move |batch| {
+            batches
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(batch);
+            ready(Ok(()))
+        }
+    }
+
+    #[must_use]
+    pub fn batch_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    #[must_use]
+    pub fn row_count(&self) -> usize {
+        self.lock().iter().map(Vec::len).sum()
+    }
+
+    /// Removes and returns all recorded batches.
+    #[must_use]
+    pub fn take(&self) -> Vec<Vec<T>> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Vec<T>>> {
+        self.batches.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Clone> RecordingSink<T> {
+    #[must_use]
+    pub fn batches(&self) -> Vec<Vec<T>> {
+        self.lock().clone()
+    }
+}
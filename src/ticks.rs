@@ -1,11 +1,39 @@
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+#[cfg(feature = "period_bias")]
+use rand::SeedableRng;
+#[cfg(feature = "period_bias")]
+use rand::rngs::StdRng;
+
+use crate::clock::Clock;
+use crate::scheduler::Scheduler;
+
+/// How the next tick is placed after a reschedule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Schedule {
+    /// One period after the reschedule, so slow flushes push later ticks back.
+    #[default]
+    FixedDelay,
+    /// One period after the previous tick, keeping a stable cadence. If
+    /// several periods were missed, they are skipped and the next tick is the
+    /// first one still in the future.
+    FixedRate,
+}
+
 #[derive(Debug, Clone)]
 pub struct Ticks {
     period: Option<Duration>,
+    schedule: Schedule,
     #[cfg(feature = "period_bias")]
     bias: f64,
+    #[cfg(feature = "period_bias")]
+    rng: Option<StdRng>,
+    scheduler: Option<Arc<dyn Scheduler>>,
     next_at: Option<Instant>,
+    disarmed: bool,
+    clock: Option<Arc<dyn Clock>>,
+    skew: Duration,
 }
 
 impl Default for Ticks {
@@ -19,9 +47,16 @@ impl Ticks {
     pub const fn new() -> Self {
         Self {
             period: None,
+            schedule: Schedule::FixedDelay,
             #[cfg(feature = "period_bias")]
             bias: 0.0,
+            #[cfg(feature = "period_bias")]
+            rng: None,
+            scheduler: None,
             next_at: None,
+            disarmed: false,
+            clock: None,
+            skew: Duration::ZERO,
         }
     }
 
@@ -31,36 +66,159 @@ impl Ticks {
         self
     }
 
+    #[must_use]
+    pub const fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    #[must_use]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Computes deadlines with `scheduler` instead of the period.
+    #[must_use]
+    pub fn with_scheduler(mut self, scheduler: Arc<dyn Scheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn now(&self) -> Instant {
+        let now = self
+            .clock
+            .as_ref()
+            .map_or_else(Instant::now, |clock| clock.now());
+        now + self.skew
+    }
+
+    /// Moves this timer's notion of now forward by `elapsed`, on top of the
+    /// clock it reads.
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.skew += elapsed;
+    }
+
+    pub const fn set_period(&mut self, period: Duration) {
+        self.period = Some(period);
+    }
+
+    #[must_use]
+    pub const fn period(&self) -> Option<Duration> {
+        self.period
+    }
+
+    #[must_use]
+    pub const fn has_period(&self) -> bool {
+        self.period.is_some()
+    }
+
+    /// Seeds the bias RNG once here so rescheduling only has to sample it.
     #[cfg(feature = "period_bias")]
     #[must_use]
-    pub const fn with_bias(mut self, bias: f64) -> Self {
+    pub fn with_bias(mut self, bias: f64) -> Self {
         self.bias = bias;
+        self.rng
+            .get_or_insert_with(|| StdRng::from_rng(&mut rand::rng()));
         self
     }
 
+    /// Sets the bias and samples it from an RNG seeded from `seed`.
+    #[cfg(feature = "period_bias")]
+    #[must_use]
+    pub fn with_seeded_bias(self, bias: f64, seed: u64) -> Self {
+        self.with_seed(seed).with_bias(bias)
+    }
+
+    /// Replaces the bias RNG with one seeded from `seed`.
+    #[cfg(feature = "period_bias")]
+    #[must_use]
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Some(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// Returns `true` with probability `rate`, sampled from the bias RNG.
+    #[cfg(feature = "period_bias")]
+    pub fn sample(&mut self, rate: f64) -> bool {
+        use rand::Rng;
+        if rate >= 1.0 {
+            return true;
+        }
+        self.rng
+            .get_or_insert_with(|| StdRng::from_rng(&mut rand::rng()))
+            .random_bool(rate.max(0.0))
+    }
+
     pub fn start(&mut self) {
-        if self.period.is_some() && self.next_at.is_none() {
+        if (self.period.is_some() || self.scheduler.is_some()) && self.next_at.is_none() {
             self.reschedule();
         }
     }
 
-    pub fn reschedule(&mut self) {
-        if let Some(period) = self.period {
+    /// Schedules the next tick. In [`Schedule::FixedRate`] mode, returns how
+    /// far past the following deadline it already is if a tick was missed.
+    pub fn reschedule(&mut self) -> Option<Duration> {
+        if self.disarmed {
+            return None;
+        }
+        let mut missed_by = None;
+        if let Some(scheduler) = &self.scheduler {
+            self.next_at = scheduler.next_deadline(self.now());
+        } else if let Some(period) = self.period {
             let actual_period = self.apply_bias(period);
-            self.next_at = Some(Instant::now() + actual_period);
+            let now = self.now();
+            self.next_at = match (self.schedule, self.next_at) {
+                (Schedule::FixedRate, Some(next_at)) if !actual_period.is_zero() => {
+                    let following = next_at + actual_period;
+                    missed_by = (following < now).then(|| now - following);
+                    Some(Self::next_on_cadence(next_at, actual_period, now))
+                }
+                _ => Some(now + actual_period),
+            };
+        }
+        missed_by
+    }
+
+    /// First tick after `now` on the cadence of `period` through `previous`.
+    fn next_on_cadence(previous: Instant, period: Duration, now: Instant) -> Instant {
+        if previous > now {
+            return previous;
         }
+        let missed = now.duration_since(previous).as_nanos() / period.as_nanos() + 1;
+        let ahead = u64::try_from(period.as_nanos() * missed).unwrap_or(u64::MAX);
+        previous + Duration::from_nanos(ahead)
+    }
+
+    /// Schedules the next tick one period from now, replacing any pending tick
+    /// and undoing [`disarm`](Self::disarm).
+    pub fn arm(&mut self) {
+        self.disarmed = false;
+        self.next_at = None;
+        self.reschedule();
+    }
+
+    /// Clears the pending tick and keeps it cleared until [`arm`](Self::arm),
+    /// without forgetting the period.
+    pub const fn disarm(&mut self) {
+        self.disarmed = true;
+        self.next_at = None;
     }
 
     #[must_use]
     pub fn reached(&self) -> bool {
+        self.next_at.is_some_and(|next_at| self.now() >= next_at)
+    }
+
+    #[must_use]
+    pub const fn next_at(&self) -> Option<Instant> {
         self.next_at
-            .is_some_and(|next_at| Instant::now() >= next_at)
     }
 
     #[must_use]
     pub fn time_left(&self) -> Option<Duration> {
         self.next_at.map(|next_at| {
-            let now = Instant::now();
+            let now = self.now();
             if now >= next_at {
                 Duration::ZERO
             } else {
@@ -69,14 +227,28 @@ impl Ticks {
         })
     }
 
+    /// How much of the current period has elapsed, in `[0, 1]`, or `None`
+    /// without a period or a pending tick.
+    #[must_use]
+    pub fn period_elapsed_fraction(&self) -> Option<f64> {
+        let period = self.period?;
+        let left = self.time_left()?;
+        if period.is_zero() {
+            return Some(1.0);
+        }
+        Some((1.0 - left.as_secs_f64() / period.as_secs_f64()).clamp(0.0, 1.0))
+    }
+
     #[cfg(feature = "period_bias")]
-    fn apply_bias(&self, period: Duration) -> Duration {
+    fn apply_bias(&mut self, period: Duration) -> Duration {
+        use rand::Rng;
+        let Some(rng) = self.rng.as_mut() else {
+            return period;
+        };
         if self.bias == 0.0 {
             return period;
         }
 
-        use rand::Rng;
-        let mut rng = rand::rng();
         let factor = 1.0 + rng.random_range(-self.bias..=self.bias);
         Duration::from_secs_f64(period.as_secs_f64() * factor)
     }
@@ -111,6 +283,152 @@ mod tests {
         assert!(ticks.reached());
     }
 
+    #[test]
+    fn test_advance() {
+        let mut ticks = Ticks::new().with_period(Duration::from_secs(60));
+        ticks.start();
+        assert!(!ticks.reached());
+
+        ticks.advance(Duration::from_secs(60));
+        assert!(ticks.reached());
+        assert_eq!(ticks.time_left(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_period_elapsed_fraction() {
+        let clock = crate::test_util::MockClock::new();
+        let mut ticks = Ticks::new()
+            .with_period(Duration::from_secs(10))
+            .with_clock(Arc::new(clock.clone()));
+        assert_eq!(ticks.period_elapsed_fraction(), None);
+
+        ticks.start();
+        assert_eq!(ticks.period_elapsed_fraction(), Some(0.0));
+        clock.advance(Duration::from_secs(5));
+        assert!((ticks.period_elapsed_fraction().unwrap() - 0.5).abs() < 1e-9);
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(ticks.period_elapsed_fraction(), Some(1.0));
+
+        assert_eq!(Ticks::new().period_elapsed_fraction(), None);
+    }
+
+    #[test]
+    fn test_fixed_rate() {
+        let clock = crate::test_util::MockClock::new();
+        let mut ticks = Ticks::new()
+            .with_period(Duration::from_secs(10))
+            .with_schedule(Schedule::FixedRate)
+            .with_clock(Arc::new(clock.clone()));
+        ticks.start();
+
+        // A flush finishing 3s late still aims for the next 10s mark.
+        clock.advance(Duration::from_secs(13));
+        ticks.reschedule();
+        assert_eq!(ticks.time_left(), Some(Duration::from_secs(7)));
+
+        // An early reschedule keeps the pending tick.
+        clock.advance(Duration::from_secs(2));
+        ticks.reschedule();
+        assert_eq!(ticks.time_left(), Some(Duration::from_secs(5)));
+
+        // Missed ticks are skipped.
+        clock.advance(Duration::from_secs(31));
+        ticks.reschedule();
+        assert_eq!(ticks.time_left(), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn test_fixed_rate_reports_missed_ticks() {
+        let clock = crate::test_util::MockClock::new();
+        let mut ticks = Ticks::new()
+            .with_period(Duration::from_secs(10))
+            .with_schedule(Schedule::FixedRate)
+            .with_clock(Arc::new(clock.clone()));
+        ticks.start();
+
+        clock.advance(Duration::from_secs(13));
+        assert_eq!(ticks.reschedule(), None);
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(ticks.reschedule(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_fixed_delay() {
+        let clock = crate::test_util::MockClock::new();
+        let mut ticks = Ticks::new()
+            .with_period(Duration::from_secs(10))
+            .with_clock(Arc::new(clock.clone()));
+        ticks.start();
+
+        clock.advance(Duration::from_secs(13));
+        ticks.reschedule();
+        assert_eq!(ticks.time_left(), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_disarm_and_arm() {
+        let mut ticks = Ticks::new().with_period(Duration::from_millis(10));
+        ticks.start();
+
+        ticks.disarm();
+        ticks.start();
+        ticks.reschedule();
+        assert!(ticks.time_left().is_none());
+        assert_eq!(ticks.period(), Some(Duration::from_millis(10)));
+
+        ticks.arm();
+        assert!(ticks.time_left().is_some());
+    }
+
+    #[cfg(feature = "period_bias")]
+    #[test]
+    fn test_bias_rng_is_kept_between_calls() {
+        let period = Duration::from_secs(10);
+        let mut ticks = Ticks::new().with_period(period).with_bias(0.5);
+        let mut copy = ticks.clone();
+
+        for _ in 0..16 {
+            let biased = ticks.apply_bias(period);
+            assert_eq!(biased, copy.apply_bias(period));
+            assert!(biased >= period / 2 && biased <= period * 3 / 2);
+        }
+    }
+
+    #[cfg(feature = "period_bias")]
+    #[test]
+    fn test_seeded_bias_is_reproducible() {
+        let period = Duration::from_secs(10);
+        let biased = |seed| {
+            let mut ticks = Ticks::new().with_seed(seed).with_bias(0.5);
+            (0..8).map(|_| ticks.apply_bias(period)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(biased(7), biased(7));
+        assert_ne!(biased(7), biased(8));
+    }
+
+    #[derive(Debug)]
+    struct Fixed(Instant);
+
+    impl Scheduler for Fixed {
+        fn next_deadline(&self, _now: Instant) -> Option<Instant> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn test_scheduler_overrides_period() {
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let mut ticks = Ticks::new()
+            .with_period(Duration::from_millis(10))
+            .with_scheduler(Arc::new(Fixed(deadline)));
+        ticks.start();
+
+        std::thread::sleep(Duration::from_millis(15));
+        assert!(!ticks.reached());
+        assert!(ticks.time_left().unwrap() > Duration::from_secs(59));
+    }
+
     #[test]
     fn test_reschedule() {
         let mut ticks = Ticks::new().with_period(Duration::from_millis(10));
@@ -0,0 +1,58 @@
+use std::error::Error;
+use std::future::Future;
+
+use crate::inserter::Inserter;
+
+/// Guard returned by [`Inserter::transaction`].
+///
+/// Writes made through the guard form one transaction, which is closed when
+/// the guard is dropped, including on early returns. Dropping cannot flush
+/// since flushing is async; call [`commit`](Inserter::commit) afterwards as
+/// usual.
+pub struct Transaction<'a, T, F, Fut, E>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    inserter: &'a mut Inserter<T, F, Fut, E>,
+}
+
+impl<'a, T, F, Fut, E> Transaction<'a, T, F, Fut, E>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    pub(crate) const fn new(inserter: &'a mut Inserter<T, F, Fut, E>) -> Self {
+        inserter.end_transaction();
+        Self { inserter }
+    }
+
+    pub fn write_owned(&mut self, item: T) {
+        self.inserter.write_owned(item);
+    }
+}
+
+impl<T, F, Fut, E> Transaction<'_, T, F, Fut, E>
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    pub fn write(&mut self, item: &T) {
+        self.inserter.write(item);
+    }
+}
+
+impl<T, F, Fut, E> Drop for Transaction<'_, T, F, Fut, E>
+where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+    E: Error,
+{
+    fn drop(&mut self) {
+        self.inserter.end_transaction();
+    }
+}